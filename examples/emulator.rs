@@ -0,0 +1,80 @@
+//! Emulator
+//!
+//! This demo runs on any host and negotiates with an emulated 45 W laptop charger, then lowers
+//! the sink PDOs and renegotiates with a soft reset.
+
+use embedded_hal::blocking::delay::DelayMs;
+use stusb4500::caps::CapsTracker;
+use stusb4500::emulator::{chargers, Emulator};
+use stusb4500::pdo::{Pdo, PowerLimit};
+use stusb4500::trace::{PdTrace, TracedBus};
+use stusb4500::{Address, PdoChannel, STUSB4500};
+
+/// The emulator negotiates instantly
+struct NoDelay;
+
+impl DelayMs<u16> for NoDelay {
+    fn delay_ms(&mut self, _ms: u16) {}
+}
+
+fn main() {
+    let mut emulator = Emulator::new(Address::Default);
+    emulator.attach(chargers::laptop_45w());
+
+    // Trace the USB-PD traffic, with a clock advancing 1 ms per entry
+    let mut time = 0;
+    let trace = PdTrace::<_, 8>::new(move || {
+        time += 1000;
+        time
+    });
+    let mut bus = TracedBus::new(emulator, Address::Default, trace);
+
+    // Read each message as it arrives, like an alert handler would
+    let mut mcu = STUSB4500::with_bus(&mut bus, Address::Default);
+    while let Some(message) = mcu.poll_rx_message().unwrap() {
+        println!("Received: {}", message.kind());
+    }
+    println!("PE state: {}", mcu.get_pe_state().unwrap().name());
+    println!("Contract: {}", mcu.get_current_rdo().unwrap());
+
+    // Ask for 9 V instead and renegotiate (voltage in 50 mV, current in 10 mA units)
+    mcu.set_sink_pdos(&[Pdo::new_fixed(100, 150), Pdo::new_fixed(180, 200)])
+        .unwrap();
+    mcu.soft_reset().unwrap();
+    while mcu.poll_rx_message().unwrap().is_some() {}
+    println!("Contract: {}", mcu.get_current_rdo().unwrap());
+    print!("{}", bus.trace().text());
+    print!("{}", bus.trace().csv());
+
+    let (mut emulator, _) = bus.release();
+    println!("Outcome: {:?}", emulator.last_outcome());
+
+    // A 5 V only charger can not provide 9 V
+    let mut tracker = CapsTracker::new();
+    tracker.observe(*emulator.source_capabilities().unwrap());
+    emulator.send_source_capabilities(chargers::phone_5v());
+    let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+    if let Some(change) = tracker.poll(&mut mcu).unwrap() {
+        println!("{} (reduction: {})", change, change.is_reduction());
+    }
+    println!("Contract: {}", mcu.get_current_rdo().unwrap());
+
+    // Ask for at most 4 W, which is 800 mA at 5 V
+    let rdo = mcu
+        .set_power_limit(PowerLimit::Power(4000), &mut NoDelay)
+        .unwrap();
+    println!("Derated contract: {}", rdo);
+    for pdo in mcu.get_sink_pdos().unwrap().iter() {
+        println!("Sink PDO: {}", pdo);
+    }
+
+    // Write the NVM and read it back
+    let mut nvm = mcu.unlock_nvm().unwrap();
+    let mut config = nvm.read_config().unwrap();
+    config.set_pdo(PdoChannel::PDO2, 12000, 1500).unwrap();
+    nvm.write_config(&config).unwrap();
+    let sectors = nvm.read_sectors().unwrap();
+    nvm.lock().unwrap();
+    assert_eq!(sectors, emulator.nvm());
+    println!("NVM: {:02x?}", sectors);
+}
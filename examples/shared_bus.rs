@@ -0,0 +1,52 @@
+//! Shared bus
+//!
+//! This demo is intended to run on a linux host (e.g. a Raspberry Pi) and shows how to use the
+//! driver on a bus that is shared with other devices, either by borrowing the bus per call or by
+//! moving a bus proxy into the driver. The proxy works like the ones handed out by
+//! [`shared-bus`](https://github.com/Rahix/shared-bus).
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Read, Write};
+use linux_embedded_hal::I2cdev;
+use stusb4500::{Address, STUSB4500};
+
+/// Proxy handing out access to a bus shared through a `RefCell`
+struct BusProxy<'a, I2C>(&'a RefCell<I2C>);
+
+impl<I2C: Write> Write for BusProxy<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<I2C: Read> Read for BusProxy<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().read(address, buffer)
+    }
+}
+
+fn main() {
+    let mut i2c = I2cdev::new("/dev/i2c-1").unwrap();
+
+    // Borrow the bus for a single call
+    let alerts = STUSB4500::with_bus(&mut i2c, Address::Default)
+        .get_alerts()
+        .unwrap();
+    println!("Alerts: {:?}", alerts);
+
+    // The bus is free to use for other devices here
+
+    // Move a proxy into the driver, other devices get their own proxy
+    let bus = RefCell::new(i2c);
+    let mut mcu = STUSB4500::new(BusProxy(&bus), Address::Default);
+    let _other = BusProxy(&bus);
+    println!("RDO: {:?}", mcu.get_current_rdo().unwrap());
+
+    // Get the proxy back when done
+    let _proxy = mcu.release();
+}
@@ -0,0 +1,166 @@
+//! Source capability change detection
+//!
+//! Sources can resend Source_Capabilities at any time, e.g. a multi-port charger offering less
+//! power once a second device is plugged in. The STUSB4500 renegotiates on its own, but the
+//! application may need to derate its load. [`CapsTracker`] caches the last capabilities and
+//! reports when they change. It has to see every Source_Capabilities message, so feed it from the
+//! handler of the [`Alert::PRTStatus`](crate::registers::Alert::PRTStatus) alert.
+
+use core::fmt;
+
+use hal::blocking::i2c;
+
+use crate::message::Message;
+use crate::pdo::SourceCapabilities;
+use crate::{Error, STUSB4500};
+
+/// Capabilities changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CapsChange {
+    /// Previous capabilities, `None` for the first capabilities after attach
+    pub old: Option<SourceCapabilities>,
+    pub new: SourceCapabilities,
+}
+
+impl CapsChange {
+    /// The highest offered power went down
+    pub fn is_reduction(&self) -> bool {
+        self.old
+            .is_some_and(|old| self.new.max_power() < old.max_power())
+    }
+}
+
+impl fmt::Display for CapsChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.old {
+            Some(old) => write!(
+                f,
+                "source capabilities changed from {} mW to {} mW",
+                old.max_power(),
+                self.new.max_power()
+            ),
+            None => write!(
+                f,
+                "source capabilities received, {} mW",
+                self.new.max_power()
+            ),
+        }
+    }
+}
+
+/// Caches the last source capabilities and detects changes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CapsTracker {
+    current: Option<SourceCapabilities>,
+}
+
+impl CapsTracker {
+    pub fn new() -> Self {
+        CapsTracker { current: None }
+    }
+
+    /// Last received capabilities
+    pub fn current(&self) -> Option<&SourceCapabilities> {
+        self.current.as_ref()
+    }
+
+    /// Forget the cached capabilities, e.g. after the source was detached
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    /// Check for newly received capabilities
+    ///
+    /// Must be called from the handler of the [`Alert::PRTStatus`](crate::registers::Alert::PRTStatus) alert, once per alert. The
+    /// RX registers only hold the last received message, and the Accept and PS_RDY that follow
+    /// Source_Capabilities within milliseconds overwrite it. Polling periodically instead misses
+    /// changes.
+    ///
+    /// Uses [`STUSB4500::poll_rx_message`], which clears `PRT_STATUS`. When the application
+    /// handles the received messages itself, pass them to [`Self::observe_message`] instead.
+    pub fn poll<I2C, E>(&mut self, mcu: &mut STUSB4500<I2C>) -> Result<Option<CapsChange>, Error<E>>
+    where
+        I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    {
+        Ok(mcu
+            .poll_rx_message()?
+            .and_then(|message| self.observe_message(&message)))
+    }
+
+    /// Feed a received message, other messages than Source_Capabilities are ignored
+    pub fn observe_message(&mut self, message: &Message) -> Option<CapsChange> {
+        message
+            .source_capabilities()
+            .and_then(|caps| self.observe(caps))
+    }
+
+    /// Feed decoded capabilities, returns the change if they differ from the cached ones
+    pub fn observe(&mut self, caps: SourceCapabilities) -> Option<CapsChange> {
+        if self
+            .current
+            .is_some_and(|current| current.as_slice() == caps.as_slice())
+        {
+            return None;
+        }
+        let old = self.current.replace(caps);
+        Some(CapsChange { old, new: caps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{chargers, Emulator};
+    use crate::registers::Alert;
+    use crate::{Address, BusRef};
+
+    fn on_alert(
+        mcu: &mut STUSB4500<BusRef<'_, Emulator>>,
+        tracker: &mut CapsTracker,
+    ) -> Option<CapsChange> {
+        if mcu.get_alerts().unwrap().contains(Alert::PRTStatus) {
+            tracker.poll(mcu).unwrap()
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn alert_handler_detects_change_before_accept_and_ps_rdy() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut tracker = CapsTracker::new();
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let change = on_alert(&mut mcu, &mut tracker).unwrap();
+        assert_eq!(change.old, None);
+        while mcu.poll_rx_message().unwrap().is_some() {}
+
+        emulator.send_source_capabilities(chargers::phone_5v());
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let change = on_alert(&mut mcu, &mut tracker).unwrap();
+        assert_eq!(change.old, Some(chargers::laptop_45w()));
+        assert_eq!(change.new, chargers::phone_5v());
+        assert!(change.is_reduction());
+        // Accept and PS_RDY raise the alert as well, but carry no capabilities
+        assert_eq!(on_alert(&mut mcu, &mut tracker), None);
+        assert_eq!(on_alert(&mut mcu, &mut tracker), None);
+        assert!(!mcu.get_alerts().unwrap().contains(Alert::PRTStatus));
+        assert_eq!(tracker.current(), Some(&chargers::phone_5v()));
+    }
+
+    #[test]
+    fn late_poll_misses_change() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut tracker = CapsTracker::new();
+        tracker.observe(chargers::laptop_45w());
+
+        emulator.send_source_capabilities(chargers::phone_5v());
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        // Any other access lets the negotiation complete, PS_RDY overwrites the capabilities
+        mcu.get_current_rdo().unwrap();
+        assert_eq!(tracker.poll(&mut mcu).unwrap(), None);
+        assert_eq!(tracker.current(), Some(&chargers::laptop_45w()));
+    }
+}
@@ -0,0 +1,1007 @@
+//! Emulated STUSB4500 and USB-PD source
+//!
+//! [`Emulator`] implements the I²C traits and behaves like a STUSB4500 connected to an emulated
+//! source, so negotiation logic can be tested on a host without hardware. The emulated source
+//! advertises configurable [`SourceCapabilities`], and requests are evaluated with
+//! [`evaluate_request`] following the USB-PD rules.
+//!
+//! Pass the emulator to the driver with [`STUSB4500::with_bus`](crate::STUSB4500::with_bus) to
+//! keep access to it between calls:
+//!
+//! ```
+//! use stusb4500::emulator::{chargers, Emulator};
+//! use stusb4500::{Address, STUSB4500};
+//!
+//! let mut emulator = Emulator::new(Address::Default);
+//! emulator.attach(chargers::laptop_45w());
+//!
+//! let rdo = STUSB4500::with_bus(&mut emulator, Address::Default)
+//!     .get_current_rdo()
+//!     .unwrap();
+//! assert_eq!(rdo.position(), 4);
+//! ```
+//!
+//! A negotiation is modelled as the Source_Capabilities, Accept (or Reject) and PS_RDY messages
+//! the source sends. Each message overwrites the RX registers and raises the PRT alert, and the
+//! contract is applied with the last one. The source sends its capabilities on attach; the
+//! remaining messages follow as the bus is accessed:
+//!
+//! - a read of `PRT_STATUS` or `ALERT_STATUS_1` delivers the next message once the previous one
+//!   was acknowledged by reading `PRT_STATUS`, like an alert handler keeping up with the source
+//! - a read of `PEFSM` reports the current state, then delivers the next message
+//! - reading the RX registers has no effect
+//! - any other access delivers the remaining messages first, so a late reader finds PS_RDY in
+//!   the RX registers like on real hardware
+//!
+//! After a soft reset the source resends its capabilities, and `PEFSM` reports the reset in
+//! progress until then.
+
+use core::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+use hal::blocking::i2c;
+
+use crate::message::{ControlMessage, DataMessage, Header, Message, PdCommand};
+use crate::nvm::NvmConfig;
+use crate::pdo::{Pdo, SourceCapabilities, SourcePdo};
+use crate::rdo::Rdo;
+use crate::registers::*;
+use crate::{Address, DEFAULT_NVM_PASSWORD};
+
+/// Factory default NVM content
+pub const DEFAULT_NVM: [[u8; 8]; 5] = [
+    [0x00, 0x00, 0xB0, 0xAB, 0x00, 0x45, 0x00, 0x00],
+    [0x10, 0x40, 0x9C, 0x1C, 0xFF, 0x01, 0x3C, 0xDF],
+    [0x02, 0x40, 0x0F, 0x00, 0x32, 0x00, 0xFC, 0xF1],
+    [0x00, 0x19, 0x56, 0xAF, 0xF5, 0x35, 0x5F, 0x00],
+    [0x00, 0x4B, 0x90, 0x21, 0x43, 0x00, 0x40, 0xFB],
+];
+
+const DEVICE_ID: u8 = 0x25;
+
+/// Capabilities of common chargers
+pub mod chargers {
+    use crate::pdo::{SourceCapabilities, SourcePdo};
+
+    fn fixed(voltage: u16, current: u16) -> SourcePdo {
+        SourcePdo::Fixed { voltage, current }
+    }
+
+    /// 45 W laptop power supply
+    pub fn laptop_45w() -> SourceCapabilities {
+        SourceCapabilities::new(&[
+            fixed(5000, 3000),
+            fixed(9000, 3000),
+            fixed(15000, 3000),
+            fixed(20000, 2250),
+        ])
+        .unwrap()
+    }
+
+    /// 65 W laptop power supply with a programmable power supply PDO
+    pub fn laptop_65w_pps() -> SourceCapabilities {
+        SourceCapabilities::new(&[
+            fixed(5000, 3000),
+            fixed(9000, 3000),
+            fixed(15000, 3000),
+            fixed(20000, 3250),
+            SourcePdo::Pps {
+                min_voltage: 3300,
+                max_voltage: 21000,
+                current: 3250,
+            },
+        ])
+        .unwrap()
+    }
+
+    /// USB-PD phone charger that only offers 5 V
+    pub fn phone_5v() -> SourceCapabilities {
+        SourceCapabilities::new(&[fixed(5000, 2000)]).unwrap()
+    }
+}
+
+/// Response of a source to a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RequestOutcome {
+    Accept,
+    /// Accepted, but the sink flagged that it needs more than is offered
+    CapabilityMismatch,
+    Reject,
+}
+
+/// Evaluate a request against the source capabilities following the USB-PD rules
+///
+/// The request is rejected if it refers to a missing object position, asks for more operating
+/// current (or power) than the PDO offers, or asks for a higher maximum without setting the
+/// capability mismatch flag. Requests for programmable power supply PDOs are rejected as the
+/// STUSB4500 can not issue them.
+pub fn evaluate_request(caps: &SourceCapabilities, rdo: &Rdo) -> RequestOutcome {
+    let limit = match caps.position(rdo.position() as usize) {
+        // Current in 10 mA units
+        Some(SourcePdo::Fixed { current, .. }) | Some(SourcePdo::Variable { current, .. }) => {
+            *current as u32 / 10
+        }
+        // Power in 250 mW units, using the same request fields
+        Some(SourcePdo::Battery { power, .. }) => power / 250,
+        Some(SourcePdo::Pps { .. }) | None => return RequestOutcome::Reject,
+    };
+
+    if rdo.operating_current() > limit {
+        RequestOutcome::Reject
+    } else if rdo.capability_mismatch() {
+        RequestOutcome::CapabilityMismatch
+    } else if rdo.max_operating_current() > limit {
+        RequestOutcome::Reject
+    } else {
+        RequestOutcome::Accept
+    }
+}
+
+/// Build the request the STUSB4500 sends for a set of sink PDOs
+///
+/// The highest sink PDO with a fixed source PDO of the same voltage and sufficient current is
+/// requested. If there is none, 5 V is requested with the capability mismatch flag set.
+pub fn select_request(caps: &SourceCapabilities, sink_pdos: &[Pdo]) -> Rdo {
+    let request = |position: usize, operating: u32, max: u32, mismatch: bool| {
+        Rdo((position as u32) << 28 | (mismatch as u32) << 26 | operating << 10 | max)
+    };
+
+    for sink in sink_pdos.iter().rev() {
+        let sink = match sink {
+            Pdo::Fixed(fixed) => fixed,
+            _ => continue,
+        };
+        let matching = caps.iter().position(|source| match source {
+            SourcePdo::Fixed { voltage, current } => {
+                *voltage as u32 == sink.voltage() * 50 && *current as u32 >= sink.current() * 10
+            }
+            _ => false,
+        });
+        if let Some(index) = matching {
+            return request(index + 1, sink.current(), sink.current(), false);
+        }
+    }
+
+    let wanted = match sink_pdos.first() {
+        Some(Pdo::Fixed(fixed)) => fixed.current(),
+        _ => 0,
+    };
+    let offered = match caps.position(1) {
+        Some(SourcePdo::Fixed { current, .. }) => *current as u32 / 10,
+        _ => 0,
+    };
+    request(1, wanted.min(offered), wanted, true)
+}
+
+/// Errors returned by the emulated bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EmulatorError {
+    /// Transaction addressed to another device
+    Nack,
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::Nack => write!(f, "address not acknowledged"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EmulatorError {}
+
+#[derive(Clone, Copy)]
+enum Source {
+    TypeC,
+    Pd(SourceCapabilities),
+}
+
+/// Next message the source sends in a negotiation
+enum Pending {
+    SourceCapabilities,
+    Accept(Rdo, RequestOutcome),
+    PsRdy(Rdo, RequestOutcome),
+    Reject,
+}
+
+/// Emulated STUSB4500 with an emulated source attached to it
+pub struct Emulator {
+    address: u8,
+    registers: [u8; 256],
+    pointer: u8,
+    nvm: [[u8; 8]; 5],
+    /// Program load register
+    plr: [u8; 8],
+    /// Sector erase register
+    ser: u8,
+    source: Option<Source>,
+    message_id: u8,
+    last_outcome: Option<RequestOutcome>,
+    pending: Option<Pending>,
+    reject_requests: bool,
+    nvm_password: u8,
+}
+
+impl Emulator {
+    /// Create a detached emulator loaded with the factory default NVM
+    pub fn new(address: Address) -> Self {
+        Emulator::with_nvm(address, DEFAULT_NVM)
+    }
+
+    /// Create a detached emulator loaded with the given NVM content
+    pub fn with_nvm(address: Address, nvm: [[u8; 8]; 5]) -> Self {
+        let mut emulator = Emulator {
+            address: address.addr(),
+            registers: [0x00; 256],
+            pointer: 0,
+            nvm,
+            plr: [0x00; 8],
+            ser: 0x00,
+            source: None,
+            message_id: 0,
+            last_outcome: None,
+            pending: None,
+            reject_requests: false,
+            nvm_password: DEFAULT_NVM_PASSWORD,
+        };
+        emulator.reset();
+        emulator
+    }
+
+    /// Reset the registers to their power-on state and load the NVM, detaching the source
+    pub fn reset(&mut self) {
+        self.registers = [0x00; 256];
+        self.source = None;
+        self.last_outcome = None;
+        self.pending = None;
+        self.registers[Register::DeviceId as usize] = DEVICE_ID;
+        self.registers[Register::BcdTypeCRevH as usize] = 0x01;
+        self.registers[Register::BcdUsbPDRevH as usize] = 0x02;
+
+        let config = NvmConfig::from_sectors(self.nvm);
+        let pdos = config.sink_pdos();
+        self.registers[Register::DPMPDONumb as usize] = pdos.len() as u8;
+        for (i, pdo) in pdos.iter().enumerate() {
+            self.set_word(Register::DPMSNKPDO1 as usize + 4 * i, pdo.bits());
+        }
+    }
+
+    /// Attach a USB-PD source and negotiate a contract
+    pub fn attach(&mut self, caps: SourceCapabilities) {
+        let rp = match caps.position(1) {
+            Some(SourcePdo::Fixed { current, .. }) if *current >= 3000 => RpCurrent::Current3A0,
+            Some(SourcePdo::Fixed { current, .. }) if *current >= 1500 => RpCurrent::Current1A5,
+            _ => RpCurrent::DefaultUsb,
+        };
+        self.complete();
+        self.connect(rp);
+        self.source = Some(Source::Pd(caps));
+        self.negotiate();
+    }
+
+    /// Attach a Type-C source without USB-PD support
+    pub fn attach_type_c(&mut self, rp: RpCurrent) {
+        self.complete();
+        self.connect(rp);
+        self.source = Some(Source::TypeC);
+        self.set_pe_state(PeState::SnkWaitForCapabilities);
+    }
+
+    /// Detach the source
+    pub fn detach(&mut self) {
+        self.source = None;
+        self.last_outcome = None;
+        self.pending = None;
+        self.set_register(Register::PortStatus1, 0x00);
+        self.set_register(Register::CCStatus, 0b0010_0000);
+        self.set_register(Register::TypeCMonitoringStatus1, 0x00);
+        self.set_word(Register::RDORegStatus as usize, 0);
+        self.set_pe_state(PeState::SnkStartup);
+        self.raise(Register::PortStatus0, PortStatus0::AttachTrans.bits());
+        self.raise(
+            Register::TypeCMonitoringStatus0,
+            (TypeCMonitoringStatus0::VbusValidSnkTrans | TypeCMonitoringStatus0::VbusReadyTrans)
+                .bits(),
+        );
+    }
+
+    /// Let the source send new capabilities, which triggers a new negotiation
+    ///
+    /// Does nothing if no USB-PD source is attached.
+    pub fn send_source_capabilities(&mut self, caps: SourceCapabilities) {
+        if let Some(Source::Pd(_)) = self.source {
+            self.complete();
+            self.source = Some(Source::Pd(caps));
+            self.negotiate();
+        }
+    }
+
+    /// Capabilities of the attached source
+    pub fn source_capabilities(&self) -> Option<&SourceCapabilities> {
+        match &self.source {
+            Some(Source::Pd(caps)) => Some(caps),
+            _ => None,
+        }
+    }
+
+    /// Let the source reject every request, e.g. while it is busy powering another port
+    pub fn reject_requests(&mut self, reject: bool) {
+        self.reject_requests = reject;
+    }
+
+    /// Set the password that unlocks the NVM, [`DEFAULT_NVM_PASSWORD`] by default
+    pub fn set_nvm_password(&mut self, password: u8) {
+        self.nvm_password = password;
+    }
+
+    /// Outcome of the last completed negotiation
+    pub fn last_outcome(&self) -> Option<RequestOutcome> {
+        self.last_outcome
+    }
+
+    /// Current value of a register
+    pub fn register(&self, register: Register) -> u8 {
+        self.registers[register as usize]
+    }
+
+    /// Current value of a word register
+    pub fn register_word(&self, register: Register) -> u32 {
+        LittleEndian::read_u32(&self.registers[register as usize..])
+    }
+
+    /// NVM content
+    pub fn nvm(&self) -> [[u8; 8]; 5] {
+        self.nvm
+    }
+
+    fn connect(&mut self, rp: RpCurrent) {
+        let cc_state = match rp {
+            RpCurrent::DefaultUsb => 0b01,
+            RpCurrent::Current1A5 => 0b10,
+            RpCurrent::Current3A0 => 0b11,
+        };
+        self.set_register(Register::CCStatus, 0b0001_0000 | cc_state);
+        self.set_register(Register::TypeCStatus, 0x00);
+        let status = PortStatus1 {
+            attached_device: AttachedDevice::Sink,
+            power_mode: PowerMode::Sink,
+            data_mode: DataMode::Ufp,
+            attached: true,
+        };
+        self.set_register(Register::PortStatus1, status.bits());
+        self.set_register(
+            Register::TypeCMonitoringStatus1,
+            (TypeCMonitoringStatus1::VbusValidSnk | TypeCMonitoringStatus1::VbusReady).bits(),
+        );
+        self.raise(Register::PortStatus0, PortStatus0::AttachTrans.bits());
+        self.raise(
+            Register::TypeCMonitoringStatus0,
+            (TypeCMonitoringStatus0::VbusValidSnkTrans | TypeCMonitoringStatus0::VbusReadyTrans)
+                .bits(),
+        );
+    }
+
+    fn sink_pdos(&self) -> ([Pdo; 3], usize) {
+        let mut pdos = [Pdo::new_fixed(0, 0); 3];
+        let mut len = 0;
+        let num = (self.registers[Register::DPMPDONumb as usize] & 0x07).min(3) as usize;
+        for i in 0..num {
+            let bits =
+                LittleEndian::read_u32(&self.registers[Register::DPMSNKPDO1 as usize + 4 * i..]);
+            if let Some(pdo) = Pdo::from_bits(bits) {
+                pdos[len] = pdo;
+                len += 1;
+            }
+        }
+        (pdos, len)
+    }
+
+    /// Start a negotiation, the source sends its capabilities right away
+    fn negotiate(&mut self) {
+        self.pending = Some(Pending::SourceCapabilities);
+        self.deliver();
+    }
+
+    /// Deliver the next message of the ongoing negotiation, if any
+    fn deliver(&mut self) -> bool {
+        let caps = match self.source {
+            Some(Source::Pd(caps)) => caps,
+            _ => return false,
+        };
+        match self.pending.take() {
+            None => return false,
+            Some(Pending::SourceCapabilities) => {
+                let objects: [u32; 7] =
+                    core::array::from_fn(|i| caps.get(i).map_or(0, SourcePdo::bits));
+                self.receive(
+                    DataMessage::SourceCapabilities.bits(),
+                    &objects[..caps.len()],
+                );
+                self.set_pe_state(PeState::SnkSelectCapabilities);
+
+                let (pdos, len) = self.sink_pdos();
+                let rdo = select_request(&caps, &pdos[..len]);
+                self.pending = Some(match evaluate_request(&caps, &rdo) {
+                    _ if self.reject_requests => Pending::Reject,
+                    RequestOutcome::Reject => Pending::Reject,
+                    outcome => Pending::Accept(rdo, outcome),
+                });
+            }
+            Some(Pending::Accept(rdo, outcome)) => {
+                self.receive(ControlMessage::Accept.bits(), &[]);
+                self.set_pe_state(PeState::SnkTransitionSink);
+                self.pending = Some(Pending::PsRdy(rdo, outcome));
+            }
+            Some(Pending::PsRdy(rdo, outcome)) => {
+                self.receive(ControlMessage::PsRdy.bits(), &[]);
+                self.set_word(Register::RDORegStatus as usize, rdo.0);
+                self.set_pe_state(PeState::SnkReady);
+                self.last_outcome = Some(outcome);
+            }
+            Some(Pending::Reject) => {
+                self.receive(ControlMessage::Reject.bits(), &[]);
+                if self.register_word(Register::RDORegStatus) != 0 {
+                    self.set_pe_state(PeState::SnkReady);
+                } else {
+                    self.set_pe_state(PeState::SnkWaitForCapabilities);
+                }
+                self.last_outcome = Some(RequestOutcome::Reject);
+            }
+        }
+        true
+    }
+
+    /// Deliver the remaining messages of the ongoing negotiation
+    fn complete(&mut self) {
+        while self.deliver() {}
+    }
+
+    fn receive(&mut self, message_type: u8, objects: &[u32]) {
+        let mut header = Header(0);
+        header.set_message_type(message_type);
+        header.set_spec_revision(0b10);
+        header.set_port_power_role(true);
+        header.set_message_id(self.message_id);
+        self.message_id = (self.message_id + 1) & 0x07;
+        let message = Message::new(header, objects);
+
+        let start = Register::RXHeaderL as usize;
+        LittleEndian::write_u16(&mut self.registers[start..], message.header.0);
+        let objects = &mut self.registers[Register::RXDataObj as usize..][..28];
+        objects.fill(0);
+        for (raw, object) in objects.chunks_exact_mut(4).zip(message.data_objects()) {
+            LittleEndian::write_u32(raw, *object);
+        }
+        self.raise(Register::PRTStatus, PrtStatus::PrlMessageReceived.bits());
+    }
+
+    fn set_pe_state(&mut self, state: PeState) {
+        self.set_register(Register::PEFSM, state.bits());
+    }
+
+    fn set_register(&mut self, register: Register, value: u8) {
+        self.registers[register as usize] = value;
+    }
+
+    fn set_word(&mut self, address: usize, value: u32) {
+        LittleEndian::write_u32(&mut self.registers[address..], value);
+    }
+
+    /// Latch bits in a read-clear register and raise the corresponding alert
+    fn raise(&mut self, register: Register, bits: u8) {
+        self.registers[register as usize] |= bits;
+        if let Some(alert) = Emulator::alert_for(register) {
+            self.registers[Register::AlertStatus1 as usize] |= alert.bits();
+        }
+    }
+
+    fn alert_for(register: Register) -> Option<Alert> {
+        match register {
+            Register::PortStatus0 => Some(Alert::PortStatus),
+            Register::TypeCMonitoringStatus0 => Some(Alert::TypeCMonitoringStatus),
+            Register::CCHWFaultStatus0 => Some(Alert::CCHWFaultStatus),
+            Register::PRTStatus => Some(Alert::PRTStatus),
+            _ => None,
+        }
+    }
+
+    fn read_register(&mut self, address: u8) -> u8 {
+        let value = self.registers[address as usize];
+        if let Some(register) = Register::containing(address) {
+            if let Some(alert) = Emulator::alert_for(register) {
+                self.registers[address as usize] = 0;
+                self.registers[Register::AlertStatus1 as usize] &= !alert.bits();
+            }
+        }
+        value
+    }
+
+    fn write_register(&mut self, address: u8, value: u8) {
+        let register = match Register::containing(address) {
+            Some(register) => register,
+            None => return,
+        };
+        match register {
+            Register::AlertStatus1Mask
+            | Register::MonitoringCtrl0
+            | Register::MonitoringCtrl2
+            | Register::ResetCtrl
+            | Register::VbusDischargeTimeCtrl
+            | Register::VbusDischargeCtrl
+            | Register::VbusCtrl
+            | Register::GpioSWGpio
+            | Register::TXHeaderL
+            | Register::TXHeaderH
+            | Register::RWBuffer
+            | Register::DPMPDONumb
+            | Register::DPMSNKPDO1
+            | Register::DPMSNKPDO2
+            | Register::DPMSNKPDO3
+            | Register::NvmPassword
+            | Register::NvmCtrl1 => self.registers[address as usize] = value,
+            Register::PDCommandCtrl => {
+                self.registers[address as usize] = value;
+                let command = PdCommand {
+                    tx_header: self.registers[Register::TXHeaderL as usize],
+                    command: value,
+                };
+                if command == PdCommand::SOFT_RESET && matches!(self.source, Some(Source::Pd(_))) {
+                    self.pending = Some(Pending::SourceCapabilities);
+                    self.set_pe_state(PeState::SendSoftReset);
+                }
+            }
+            Register::NvmCtrl0 => self.write_nvm_ctrl0(value),
+            _ => {}
+        }
+    }
+
+    fn write_nvm_ctrl0(&mut self, value: u8) {
+        if self.registers[Register::NvmPassword as usize] != self.nvm_password {
+            return;
+        }
+        let ctrl = NvmCtrl0::from_bits_truncate(value);
+        if ctrl.contains(NvmCtrl0::Power | NvmCtrl0::Enable | NvmCtrl0::Request) {
+            self.nvm_request(value & 0x07);
+        }
+        self.registers[Register::NvmCtrl0 as usize] = value & !NvmCtrl0::Request.bits();
+    }
+
+    fn nvm_request(&mut self, sector: u8) {
+        let ctrl1 = self.registers[Register::NvmCtrl1 as usize];
+        let buffer = Register::RWBuffer as usize;
+        let sector = (sector as usize).min(4);
+        match ctrl1 & 0x07 {
+            op if op == NvmCtrl1Opcode::ReadSector as u8 => {
+                self.registers[buffer..buffer + 8].copy_from_slice(&self.nvm[sector])
+            }
+            op if op == NvmCtrl1Opcode::LoadPlr as u8 => self
+                .plr
+                .copy_from_slice(&self.registers[buffer..buffer + 8]),
+            op if op == NvmCtrl1Opcode::LoadSer as u8 => self.ser = ctrl1 & 0xF8,
+            op if op == NvmCtrl1Opcode::EraseSectors as u8 => {
+                for (i, data) in self.nvm.iter_mut().enumerate() {
+                    if self.ser & (NvmCtrl1::EraseSector0.bits() << i) != 0 {
+                        *data = [0x00; 8];
+                    }
+                }
+            }
+            op if op == NvmCtrl1Opcode::WriteSector as u8 => self.nvm[sector] = self.plr,
+            _ => {}
+        }
+    }
+}
+
+impl i2c::Write for Emulator {
+    type Error = EmulatorError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), EmulatorError> {
+        if address != self.address {
+            return Err(EmulatorError::Nack);
+        }
+        if let Some((pointer, data)) = bytes.split_first() {
+            self.pointer = *pointer;
+            if !data.is_empty() {
+                self.complete();
+            }
+            for value in data {
+                self.write_register(self.pointer, *value);
+                self.pointer = self.pointer.wrapping_add(1);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl i2c::Read for Emulator {
+    type Error = EmulatorError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), EmulatorError> {
+        if address != self.address {
+            return Err(EmulatorError::Nack);
+        }
+        let start = self.pointer;
+        let acknowledged = self.registers[Register::PRTStatus as usize]
+            & PrtStatus::PrlMessageReceived.bits()
+            == 0;
+        match Register::containing(start) {
+            Some(Register::PRTStatus | Register::AlertStatus1) if acknowledged => {
+                self.deliver();
+            }
+            Some(
+                Register::PRTStatus
+                | Register::AlertStatus1
+                | Register::PEFSM
+                | Register::RXHeaderL
+                | Register::RXHeaderH
+                | Register::RXDataObj,
+            ) => {}
+            _ => self.complete(),
+        }
+        for value in buffer.iter_mut() {
+            *value = self.read_register(self.pointer);
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+        if start == Register::PEFSM as u8 {
+            self.deliver();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MessageKind;
+    use crate::pdo::PowerLimit;
+    use crate::{Error, RenegotiationFailure, STUSB4500};
+    use hal::blocking::delay::DelayMs;
+
+    struct NoDelay;
+
+    impl DelayMs<u16> for NoDelay {
+        fn delay_ms(&mut self, _ms: u16) {}
+    }
+
+    /// Bus failing the first read of `PEFSM`
+    struct FailPeState<'a> {
+        emulator: &'a mut Emulator,
+        pointer: u8,
+        failed: bool,
+    }
+
+    impl i2c::Write for FailPeState<'_> {
+        type Error = EmulatorError;
+
+        fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), EmulatorError> {
+            if let Some(pointer) = bytes.first() {
+                self.pointer = *pointer;
+            }
+            self.emulator.write(address, bytes)
+        }
+    }
+
+    impl i2c::Read for FailPeState<'_> {
+        type Error = EmulatorError;
+
+        fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), EmulatorError> {
+            if !self.failed && self.pointer == Register::PEFSM as u8 {
+                self.failed = true;
+                return Err(EmulatorError::Nack);
+            }
+            self.emulator.read(address, buffer)
+        }
+    }
+
+    fn pps_only() -> SourceCapabilities {
+        SourceCapabilities::new(&[SourcePdo::Pps {
+            min_voltage: 3300,
+            max_voltage: 11000,
+            current: 3000,
+        }])
+        .unwrap()
+    }
+
+    #[test]
+    fn laptop_45w_negotiates_highest_sink_pdo() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let rdo = mcu.get_current_rdo().unwrap();
+        assert_eq!(rdo.position(), 4);
+        assert_eq!(rdo.operating_current(), 100);
+        assert!(!rdo.capability_mismatch());
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkReady);
+        // Source_Capabilities was overwritten by the messages that followed it
+        let message = mcu.get_rx_message().unwrap();
+        assert_eq!(message.kind(), MessageKind::Control(ControlMessage::PsRdy));
+        assert_eq!(emulator.last_outcome(), Some(RequestOutcome::Accept));
+    }
+
+    #[test]
+    fn alert_handler_receives_each_message() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let message = mcu.poll_rx_message().unwrap().unwrap();
+        assert_eq!(message.source_capabilities(), Some(chargers::laptop_45w()));
+        let message = mcu.poll_rx_message().unwrap().unwrap();
+        assert_eq!(message.kind(), MessageKind::Control(ControlMessage::Accept));
+        let message = mcu.poll_rx_message().unwrap().unwrap();
+        assert_eq!(message.kind(), MessageKind::Control(ControlMessage::PsRdy));
+        assert!(mcu.poll_rx_message().unwrap().is_none());
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkReady);
+    }
+
+    #[test]
+    fn pe_state_steps_through_negotiation() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkSelectCapabilities);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkTransitionSink);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkReady);
+        assert_eq!(mcu.get_current_rdo().unwrap().position(), 4);
+
+        mcu.soft_reset().unwrap();
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SendSoftReset);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkSelectCapabilities);
+    }
+
+    #[test]
+    fn soft_reset_renegotiates_new_sink_pdos() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        mcu.set_sink_pdos(&[Pdo::new_fixed(100, 150), Pdo::new_fixed(180, 300)])
+            .unwrap();
+        assert_eq!(mcu.get_current_rdo().unwrap().position(), 4);
+        mcu.soft_reset().unwrap();
+        let rdo = mcu.get_current_rdo().unwrap();
+        assert_eq!(rdo.position(), 2);
+        assert_eq!(rdo.max_operating_current(), 300);
+    }
+
+    #[test]
+    fn phone_5v_falls_back_to_pdo1() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::phone_5v());
+
+        let rdo = STUSB4500::with_bus(&mut emulator, Address::Default)
+            .get_current_rdo()
+            .unwrap();
+        assert_eq!(rdo.position(), 1);
+        assert_eq!(rdo.operating_current(), 150);
+        assert!(!rdo.capability_mismatch());
+        assert_eq!(emulator.last_outcome(), Some(RequestOutcome::Accept));
+    }
+
+    #[test]
+    fn capability_mismatch_when_no_pdo_fits() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(
+            SourceCapabilities::new(&[SourcePdo::Fixed {
+                voltage: 5000,
+                current: 1000,
+            }])
+            .unwrap(),
+        );
+
+        let rdo = STUSB4500::with_bus(&mut emulator, Address::Default)
+            .get_current_rdo()
+            .unwrap();
+        assert_eq!(rdo.position(), 1);
+        assert_eq!(rdo.operating_current(), 100);
+        assert_eq!(rdo.max_operating_current(), 150);
+        assert!(rdo.capability_mismatch());
+        assert_eq!(
+            emulator.last_outcome(),
+            Some(RequestOutcome::CapabilityMismatch)
+        );
+    }
+
+    #[test]
+    fn reject_without_contract_waits_for_capabilities() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(pps_only());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        assert_eq!(mcu.get_current_rdo().unwrap().0, 0);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkWaitForCapabilities);
+        let message = mcu.get_rx_message().unwrap();
+        assert_eq!(message.kind(), MessageKind::Control(ControlMessage::Reject));
+        assert_eq!(emulator.last_outcome(), Some(RequestOutcome::Reject));
+    }
+
+    #[test]
+    fn reject_keeps_previous_contract() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let contract = STUSB4500::with_bus(&mut emulator, Address::Default)
+            .get_current_rdo()
+            .unwrap()
+            .0;
+        emulator.send_source_capabilities(pps_only());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        assert_eq!(mcu.get_current_rdo().unwrap().0, contract);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkReady);
+        assert_eq!(emulator.last_outcome(), Some(RequestOutcome::Reject));
+    }
+
+    #[test]
+    fn power_limit_derates_contract() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::phone_5v());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let limit = PowerLimit::Power(4000);
+        let rdo = mcu.set_power_limit(limit, &mut NoDelay).unwrap();
+        assert_eq!(rdo.position(), 1);
+        assert_eq!(rdo.operating_current(), 80);
+        assert_eq!(mcu.get_current_rdo().unwrap().0, rdo.0);
+        assert!(mcu.get_sink_pdos().unwrap().iter().all(|pdo| match pdo {
+            Pdo::Fixed(fixed) => fixed.current() <= 80,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn power_limit_not_met_restores_pdos() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let contract = STUSB4500::with_bus(&mut emulator, Address::Default)
+            .get_current_rdo()
+            .unwrap();
+        emulator.reject_requests(true);
+
+        // The source keeps the 20 V 1 A contract instead of granting 750 mA
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let previous = mcu.get_sink_pdos().unwrap();
+        let result = mcu.set_power_limit(PowerLimit::Power(15000), &mut NoDelay);
+        match result {
+            Err(Error::LimitNotMet(rdo)) => assert_eq!(rdo.0, contract.0),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(mcu.get_sink_pdos().unwrap(), previous);
+        assert_eq!(mcu.get_current_rdo().unwrap().0, contract.0);
+        assert_eq!(mcu.get_pe_state().unwrap(), PeState::SnkReady);
+        assert_eq!(emulator.last_outcome(), Some(RequestOutcome::Reject));
+    }
+
+    #[test]
+    fn power_limit_already_met_skips_soft_reset() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let contract = mcu.get_current_rdo().unwrap();
+        let previous = mcu.get_sink_pdos().unwrap();
+        let rdo = mcu
+            .set_power_limit(PowerLimit::Current(5000), &mut NoDelay)
+            .unwrap();
+        assert_eq!(rdo.0, contract.0);
+        assert_eq!(mcu.get_sink_pdos().unwrap(), previous);
+        assert_eq!(emulator.register(Register::PDCommandCtrl), 0x00);
+    }
+
+    #[test]
+    fn power_limit_bus_error_restores_pdos() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let contract = mcu.get_current_rdo().unwrap();
+        let previous = mcu.get_sink_pdos().unwrap();
+
+        let mut bus = FailPeState {
+            emulator: &mut emulator,
+            pointer: 0,
+            failed: false,
+        };
+        let mut mcu = STUSB4500::with_bus(&mut bus, Address::Default);
+        let result = mcu.set_power_limit(PowerLimit::Power(15000), &mut NoDelay);
+        assert!(matches!(
+            result,
+            Err(Error::I2CError {
+                register: Register::PEFSM,
+                error: EmulatorError::Nack,
+                ..
+            })
+        ));
+        assert_eq!(mcu.get_sink_pdos().unwrap(), previous);
+        assert_eq!(mcu.get_current_rdo().unwrap().0, contract.0);
+    }
+
+    #[test]
+    fn power_limit_reports_failed_rollback() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(pps_only());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let previous = mcu.get_sink_pdos().unwrap();
+        let result = mcu.set_power_limit(PowerLimit::Power(4000), &mut NoDelay);
+        assert!(matches!(
+            result,
+            Err(Error::RollbackFailed {
+                cause: RenegotiationFailure::Timeout,
+                error: None,
+            })
+        ));
+        assert_eq!(mcu.get_sink_pdos().unwrap(), previous);
+    }
+
+    #[test]
+    fn evaluate_request_rules() {
+        let caps = chargers::laptop_45w();
+        let request = |position: u32, operating: u32, max: u32, mismatch: bool| {
+            Rdo(position << 28 | (mismatch as u32) << 26 | operating << 10 | max)
+        };
+        assert_eq!(
+            evaluate_request(&caps, &request(4, 225, 225, false)),
+            RequestOutcome::Accept
+        );
+        assert_eq!(
+            evaluate_request(&caps, &request(5, 100, 100, false)),
+            RequestOutcome::Reject
+        );
+        assert_eq!(
+            evaluate_request(&caps, &request(4, 300, 300, false)),
+            RequestOutcome::Reject
+        );
+        assert_eq!(
+            evaluate_request(&caps, &request(4, 225, 300, false)),
+            RequestOutcome::Reject
+        );
+        assert_eq!(
+            evaluate_request(&caps, &request(4, 225, 300, true)),
+            RequestOutcome::CapabilityMismatch
+        );
+    }
+
+    #[test]
+    fn read_clear_registers_clear_alerts() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        assert!(mcu.get_alerts().unwrap().contains(Alert::PortStatus));
+        mcu.clear_interrupts().unwrap();
+        assert_eq!(mcu.get_alerts().unwrap(), Alert::empty());
+    }
+
+    #[test]
+    fn nvm_round_trip() {
+        let mut emulator = Emulator::new(Address::Default);
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        let mut nvm = mcu.unlock_nvm().unwrap();
+        let mut config = nvm.read_config().unwrap();
+        config
+            .set_pdo(crate::PdoChannel::PDO2, 12000, 1500)
+            .unwrap();
+        nvm.write_config(&config).unwrap();
+        assert_eq!(nvm.read_config().unwrap(), config);
+        nvm.lock().unwrap();
+
+        assert!(matches!(
+            mcu.unlock_nvm_with_password(0x00),
+            Err(crate::Error::NvmLocked)
+        ));
+        assert_eq!(emulator.nvm(), config.sectors());
+    }
+
+    #[test]
+    fn nvm_accepts_configured_password() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.set_nvm_password(0x5A);
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        assert!(matches!(mcu.unlock_nvm(), Err(crate::Error::NvmLocked)));
+        let mut nvm = mcu.unlock_nvm_with_password(0x5A).unwrap();
+        assert_eq!(nvm.read_config().unwrap().sectors(), DEFAULT_NVM);
+    }
+}
@@ -0,0 +1,169 @@
+use core::fmt;
+
+use crate::pdo::Violation;
+use crate::rdo::Rdo;
+use crate::registers::{NvmCtrl1Opcode, Register};
+
+/// Direction of a register access
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Operation {
+    Read,
+    Write,
+}
+
+#[derive(Debug)]
+pub enum Error<I2C> {
+    /// Bus error while accessing a register
+    I2CError {
+        operation: Operation,
+        register: Register,
+        error: I2C,
+    },
+    /// Bus error during an NVM operation
+    NvmError {
+        sector: u8,
+        opcode: NvmCtrl1Opcode,
+        error: I2C,
+    },
+    /// PDO that can not be decoded or advertised, with its raw value
+    InvalidPdo(u32),
+    /// Number of PDOs outside of 1 to 3
+    OutaRangePdo(u8),
+    /// The NVM did not unlock, most likely because the password was rejected
+    NvmLocked,
+    /// PDO set breaking a USB-PD rule or STUSB4500 limit
+    PdoViolation(Violation),
+    /// Burst access of `len` bytes from `start` does not match the register range, or would
+    /// write a reserved address or a register that is not read-write
+    InvalidRange { start: Register, len: usize },
+    /// Negotiation did not reach a ready state in time
+    NegotiationTimeout,
+    /// Contract after renegotiation exceeds the requested limit, e.g. because the source
+    /// rejected the request and kept the previous contract. The previous PDOs were restored.
+    LimitNotMet(Rdo),
+    /// Renegotiation failed with `cause` and restoring the previous PDOs failed as well. `error`
+    /// is the bus error of the rollback, or `None` if its negotiation timed out.
+    RollbackFailed {
+        cause: RenegotiationFailure<I2C>,
+        error: Option<I2C>,
+    },
+}
+
+/// Reason a renegotiation with derated PDOs was rolled back
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RenegotiationFailure<I2C> {
+    /// Negotiation did not reach a ready state in time
+    Timeout,
+    /// Contract exceeds the requested limit
+    LimitNotMet(Rdo),
+    /// Bus error while writing the derated PDOs or renegotiating
+    I2CError {
+        operation: Operation,
+        register: Register,
+        error: I2C,
+    },
+}
+
+impl<I2C> From<RenegotiationFailure<I2C>> for Error<I2C> {
+    fn from(failure: RenegotiationFailure<I2C>) -> Self {
+        match failure {
+            RenegotiationFailure::Timeout => Error::NegotiationTimeout,
+            RenegotiationFailure::LimitNotMet(rdo) => Error::LimitNotMet(rdo),
+            RenegotiationFailure::I2CError {
+                operation,
+                register,
+                error,
+            } => Error::I2CError {
+                operation,
+                register,
+                error,
+            },
+        }
+    }
+}
+
+impl<I2C: fmt::Debug> fmt::Display for RenegotiationFailure<I2C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenegotiationFailure::Timeout => write!(f, "negotiation timed out"),
+            RenegotiationFailure::LimitNotMet(rdo) => {
+                write!(f, "contract exceeds the limit: {}", rdo)
+            }
+            RenegotiationFailure::I2CError {
+                register, error, ..
+            } => write!(
+                f,
+                "I2C error on register {:?} (0x{:02X}): {:?}",
+                register, *register as u8, error
+            ),
+        }
+    }
+}
+
+impl<I2C> Error<I2C> {
+    /// Attach NVM context to a bus error
+    pub(crate) fn in_nvm(self, sector: u8, opcode: NvmCtrl1Opcode) -> Self {
+        match self {
+            Error::I2CError { error, .. } => Error::NvmError {
+                sector,
+                opcode,
+                error,
+            },
+            err => err,
+        }
+    }
+}
+
+impl<I2C: fmt::Debug> fmt::Display for Error<I2C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::I2CError {
+                operation,
+                register,
+                error,
+            } => write!(
+                f,
+                "I2C error during {} of register {:?} (0x{:02X}): {:?}",
+                match operation {
+                    Operation::Read => "read",
+                    Operation::Write => "write",
+                },
+                register,
+                *register as u8,
+                error
+            ),
+            Error::NvmError {
+                sector,
+                opcode,
+                error,
+            } => write!(
+                f,
+                "I2C error during NVM {:?} on sector {}: {:?}",
+                opcode, sector, error
+            ),
+            Error::InvalidPdo(bits) => write!(f, "invalid PDO 0x{:08X}", bits),
+            Error::OutaRangePdo(num) => write!(f, "number of PDOs out of range: {}", num),
+            Error::NvmLocked => write!(f, "NVM is locked, password rejected"),
+            Error::PdoViolation(violation) => write!(f, "invalid PDO set: {}", violation),
+            Error::InvalidRange { start, len } => write!(
+                f,
+                "invalid burst access of {} bytes from register {:?} (0x{:02X})",
+                len, start, *start as u8
+            ),
+            Error::NegotiationTimeout => write!(f, "negotiation timed out"),
+            Error::LimitNotMet(rdo) => write!(f, "contract exceeds the limit: {}", rdo),
+            Error::RollbackFailed { cause, error } => {
+                write!(f, "{}, restoring the previous PDOs failed: ", cause)?;
+                match error {
+                    Some(error) => write!(f, "{:?}", error),
+                    None => write!(f, "negotiation timed out"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<I2C: fmt::Debug> std::error::Error for Error<I2C> {}
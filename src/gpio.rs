@@ -0,0 +1,56 @@
+//! Software controlled GPIO pin
+//!
+//! The GPIO pin is an open-drain output. Setting it low pulls the pin to ground, setting it high
+//! releases it to the external pull-up.
+
+use hal::blocking::i2c;
+use hal::digital::v2::OutputPin;
+
+use crate::{Address, Error, STUSB4500};
+
+/// GPIO pin with its own handle to the bus
+///
+/// The pin only needs a bus handle and the controller address, so it can be handed to another
+/// driver while the controller is used through a second handle, e.g. two `shared-bus` proxies.
+///
+/// The pin must be configured as [`GpioFunction::SwCtrlGpio`](crate::nvm::GpioFunction) in the
+/// NVM for writes to have an effect.
+pub struct GpioPin<I2C> {
+    inner: STUSB4500<I2C>,
+}
+
+impl<I2C, E> GpioPin<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+{
+    pub fn new(i2c: I2C, address: Address) -> Self {
+        GpioPin {
+            inner: STUSB4500::new(i2c, address),
+        }
+    }
+
+    /// Whether the pin is driven low
+    pub fn is_set_low(&mut self) -> Result<bool, Error<E>> {
+        self.inner.get_gpio()
+    }
+
+    /// Destroy the pin and return the bus handle
+    pub fn release(self) -> I2C {
+        self.inner.release()
+    }
+}
+
+impl<I2C, E> OutputPin for GpioPin<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+{
+    type Error = Error<E>;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.inner.set_gpio(true)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.inner.set_gpio(false)
+    }
+}
@@ -1,25 +1,49 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 extern crate bitflags;
 extern crate byteorder;
 extern crate embedded_hal as hal;
 
 use byteorder::{ByteOrder, LittleEndian};
+use hal::blocking::delay::DelayMs;
 use hal::blocking::i2c;
 
+pub mod caps;
+#[cfg(any(test, feature = "emulator"))]
+pub mod emulator;
+mod error;
+pub mod gpio;
+pub mod message;
+pub mod nvm;
 pub mod pdo;
+pub mod pe;
 pub mod rdo;
+pub mod recorder;
 pub mod registers;
+pub mod snapshot;
+pub mod trace;
 
+pub use error::*;
+use message::*;
+use nvm::*;
 use pdo::*;
 use rdo::*;
 use registers::*;
+use snapshot::*;
 
 pub const STUSB4500_ADDR: u8 = 0x28;
 
+/// Factory default NVM password
+pub const DEFAULT_NVM_PASSWORD: u8 = 0x47;
+
+/// Maximum number of bytes written in a single transaction
+pub const MAX_WRITE_LEN: usize = 32;
+
 /// Address enum for STUSB4500
+#[derive(Default)]
 pub enum Address {
     /// Default address with all address pins tied low
+    #[default]
     Default,
     /// Address determined by A1 and A0 pins. True = tied high, low = tied low.
     Strap(bool, bool),
@@ -31,36 +55,85 @@ impl Address {
     pub(crate) fn addr(&self) -> u8 {
         match self {
             Address::Default => STUSB4500_ADDR,
-            Address::Strap(a1, a0) => STUSB4500_ADDR | (*a1 as u8) << 1 | (*a0 as u8) << 0,
+            Address::Strap(a1, a0) => STUSB4500_ADDR | (*a1 as u8) << 1 | (*a0 as u8),
             Address::Custom(addr) => *addr,
         }
     }
 }
 
-impl Default for Address {
-    fn default() -> Self {
-        Address::Default
-    }
-}
-
-#[derive(Debug)]
-pub enum Error<I2C> {
-    I2CError(I2C),
-    InvalidPdo,
-    OutaRangePdo,
+/// Current advertisement of the attached source
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SourceCurrent {
+    /// Type-C current advertised through Rp, `None` if no source is attached
+    pub rp: Option<RpCurrent>,
+    /// Whether an explicit USB-PD contract is in place
+    pub pd_contract: bool,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PdoChannel {
     PDO1,
     PDO2,
     PDO3,
 }
 
+impl PdoChannel {
+    pub(crate) fn register(self) -> Register {
+        match self {
+            PdoChannel::PDO1 => Register::DPMSNKPDO1,
+            PdoChannel::PDO2 => Register::DPMSNKPDO2,
+            PdoChannel::PDO3 => Register::DPMSNKPDO3,
+        }
+    }
+}
+
 pub struct STUSB4500<I2C> {
     i2c: I2C,
     address: u8,
 }
 
+/// Mutably borrowed I²C bus
+///
+/// Allows the bus to be passed per call rather than moved into the driver, see
+/// [`STUSB4500::with_bus`].
+pub struct BusRef<'a, I2C>(pub &'a mut I2C);
+
+impl<I2C, E> i2c::Write for BusRef<'_, I2C>
+where
+    I2C: i2c::Write<Error = E>,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        self.0.write(address, bytes)
+    }
+}
+
+impl<I2C, E> i2c::Read for BusRef<'_, I2C>
+where
+    I2C: i2c::Read<Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        self.0.read(address, buffer)
+    }
+}
+
+impl<'a, I2C, E> STUSB4500<BusRef<'a, I2C>>
+where
+    I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+{
+    /// Create a driver borrowing the bus
+    ///
+    /// The driver holds no state besides the bus and address, so it can be created for each
+    /// transaction and dropped again to hand the bus to other devices.
+    pub fn with_bus(i2c: &'a mut I2C, address: Address) -> Self {
+        STUSB4500::new(BusRef(i2c), address)
+    }
+}
+
 impl<I2C, E> STUSB4500<I2C>
 where
     I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
@@ -72,155 +145,573 @@ where
         }
     }
 
+    /// Destroy the driver and return the I²C bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
     /// Read all interrupt registers to clear them
     pub fn clear_interrupts(&mut self) -> Result<(), Error<E>> {
         // Read all interrupt registers
         let mut _buf = [0x00; 10];
-        self.i2c
-            .write(self.address, &[Register::PortStatus0 as u8])
-            .map_err(|err| Error::I2CError(err))?;
-        self.i2c
-            .read(self.address, &mut _buf)
-            .map_err(|err| Error::I2CError(err))
+        self.read_registers(Register::PortStatus0, Register::PRTStatus, &mut _buf)?;
+        Ok(())
     }
 
     /// Set interrupt mask
+    ///
+    /// Set bits in the mask *disable* the interrupt, see [`Self::set_interrupts`] for an API in
+    /// terms of enabled alerts.
     pub fn set_alerts_mask(&mut self, alerts: AlertMask) -> Result<(), Error<E>> {
-        self.write(Register::AlertStatus1Mask, alerts.bits())
+        self.write(alerts)
+    }
+
+    /// Enable interrupts for the `enabled` alert sources and disable all others
+    ///
+    /// [`Alert::PDTypeCStatus`] has no bit in the mask register, so it is ignored here and
+    /// never reported by [`Self::get_interrupts`].
+    pub fn set_interrupts(&mut self, enabled: Alert) -> Result<(), Error<E>> {
+        self.write(AlertMask::from_enabled(enabled))
+    }
+
+    /// Get the alert sources with interrupts enabled
+    pub fn get_interrupts(&mut self) -> Result<Alert, Error<E>> {
+        Ok(self.read::<AlertMask>()?.enabled())
     }
 
     /// Get active interrupt flags
     pub fn get_alerts(&mut self) -> Result<Alert, Error<E>> {
-        Ok(Alert::from_bits_truncate(
-            self.read(Register::AlertStatus1)?,
-        ))
+        self.read()
     }
 
     pub fn get_portstatus0(&mut self) -> Result<PortStatus0, Error<E>> {
-        Ok(PortStatus0::from_bits_truncate(
-            self.read(Register::PortStatus0)?,
-        ))
+        self.read()
     }
 
     pub fn get_portstatus1(&mut self) -> Result<PortStatus1, Error<E>> {
-        Ok(PortStatus1::from_bits_truncate(
-            self.read(Register::PortStatus1)?,
-        ))
+        self.read()
     }
 
     pub fn get_typec_monitoring_status0(&mut self) -> Result<TypeCMonitoringStatus0, Error<E>> {
-        Ok(TypeCMonitoringStatus0::from_bits_truncate(
-            self.read(Register::TypeCMonitoringStatus0)?,
-        ))
+        self.read()
     }
 
     pub fn get_typec_monitoring_status1(&mut self) -> Result<TypeCMonitoringStatus1, Error<E>> {
-        Ok(TypeCMonitoringStatus1::from_bits_truncate(
-            self.read(Register::TypeCMonitoringStatus1)?,
-        ))
+        self.read()
     }
 
     pub fn get_prt_status(&mut self) -> Result<PrtStatus, Error<E>> {
-        Ok(PrtStatus::from_bits_truncate(
-            self.read(Register::PRTStatus)?,
-        ))
+        self.read()
+    }
+
+    pub fn get_cc_status(&mut self) -> Result<CcStatus, Error<E>> {
+        self.read()
+    }
+
+    pub fn get_typec_status(&mut self) -> Result<TypeCStatus, Error<E>> {
+        self.read()
+    }
+
+    /// Get the active hardware faults and the fault transitions since the last call
+    pub fn get_hw_faults(&mut self) -> Result<HwFaults, Error<E>> {
+        let mut buf = [0x00; 2];
+        self.read_registers(
+            Register::CCHWFaultStatus0,
+            Register::CCHWFaultStatus1,
+            &mut buf,
+        )?;
+        Ok(HwFaults::from_bits(buf[0], buf[1]))
+    }
+
+    /// Get the hardware faults if [`Alert::CCHWFaultStatus`] is set in `alerts`
+    ///
+    /// Reading the faults acknowledges the alert.
+    pub fn check_hw_faults(&mut self, alerts: Alert) -> Result<Option<HwFaults>, Error<E>> {
+        if alerts.contains(Alert::CCHWFaultStatus) {
+            self.get_hw_faults().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get the state of the policy engine
+    pub fn get_pe_state(&mut self) -> Result<PeState, Error<E>> {
+        self.read()
+    }
+
+    /// Get the cable orientation and the state of both CC lines
+    pub fn get_cc_info(&mut self) -> Result<CcInfo, Error<E>> {
+        let cc_status = self.get_cc_status()?;
+        let typec_status = self.get_typec_status()?;
+        Ok(CcInfo::new(cc_status, typec_status))
+    }
+
+    /// Get the current the source allows to draw
+    ///
+    /// Reports the Type-C current advertised through the source's Rp termination and whether an
+    /// explicit USB-PD contract has been negotiated. Without a contract the Type-C current is
+    /// the limit, e.g. on chargers without USB-PD.
+    pub fn get_source_current(&mut self) -> Result<SourceCurrent, Error<E>> {
+        let rp = self.get_cc_status()?.rp_current();
+        let pd_contract = rp.is_some() && self.get_current_rdo()?.position() != 0;
+        Ok(SourceCurrent { rp, pd_contract })
+    }
+
+    /// Get the VBUS monitoring window of the active contract
+    ///
+    /// The controller loads this window from the NVM tolerances of the negotiated PDO, see
+    /// [`NvmConfig::upper_tolerance`] and [`NvmConfig::lower_tolerance`].
+    pub fn get_vbus_monitoring(&mut self) -> Result<MonitoringCtrl2, Error<E>> {
+        self.read()
+    }
+
+    /// Override the VBUS monitoring window of the active contract
+    ///
+    /// The controller may reload the window from NVM when a new contract is negotiated.
+    pub fn set_vbus_monitoring(&mut self, window: MonitoringCtrl2) -> Result<(), Error<E>> {
+        self.write(window)
+    }
+
+    /// Read the complete register file in a single transaction
+    ///
+    /// Note that this clears the latched interrupt registers, like [`Self::clear_interrupts`].
+    pub fn snapshot(&mut self) -> Result<RegisterSnapshot, Error<E>> {
+        let mut raw = [0x00; SNAPSHOT_LEN];
+        self.read_registers(SNAPSHOT_START, Register::NvmCtrl1, &mut raw)?;
+        Ok(RegisterSnapshot::from_raw(raw))
+    }
+
+    /// Read the last received USB-PD message
+    ///
+    /// The message is valid after [`PrtStatus::PrlMessageReceived`] has been flagged, until the
+    /// next message is received.
+    pub fn get_rx_message(&mut self) -> Result<Message, Error<E>> {
+        let mut buf = [0x00; 2 + 4 * MAX_DATA_OBJECTS];
+        self.read_registers(Register::RXHeaderL, Register::RXDataObj, &mut buf)?;
+        let mut objects = [0; MAX_DATA_OBJECTS];
+        for (object, raw) in objects.iter_mut().zip(buf[2..].chunks_exact(4)) {
+            *object = LittleEndian::read_u32(raw);
+        }
+        let header = Header(LittleEndian::read_u16(&buf));
+        let len = (header.num_data_objects() as usize).min(MAX_DATA_OBJECTS);
+        Ok(Message::new(header, &objects[..len]))
+    }
+
+    /// Read the received USB-PD message if one arrived since `PRT_STATUS` was last read
+    ///
+    /// Reading `PRT_STATUS` clears it, so use this as the single poll for received messages and
+    /// pass the message on, e.g. to a [`CapsTracker`](caps::CapsTracker).
+    pub fn poll_rx_message(&mut self) -> Result<Option<Message>, Error<E>> {
+        if !self
+            .get_prt_status()?
+            .contains(PrtStatus::PrlMessageReceived)
+        {
+            return Ok(None);
+        }
+        self.get_rx_message().map(Some)
     }
 
     /// Perform a soft reset
     /// Triggers re-negotiation of PDO's.
     pub fn soft_reset(&mut self) -> Result<(), Error<E>> {
-        self.write(Register::TXHeaderL, 0x0D)?;
-        self.write(Register::PDCommandCtrl, 0x26)?;
-        Ok(())
+        self.send_pd_command(PdCommand::SOFT_RESET)
     }
 
+    /// Write a command to `PD_COMMAND_CTRL`, after its TX header
+    pub fn send_pd_command(&mut self, command: PdCommand) -> Result<(), Error<E>> {
+        self.write_byte(Register::TXHeaderL, command.tx_header)?;
+        self.write_byte(Register::PDCommandCtrl, command.command)
+    }
+
+    /// Write a single sink PDO
+    ///
+    /// The PDO is checked with [`validate_sink_pdo`] at the position of `pdo`. Use
+    /// [`Self::set_sink_pdos`] to check the ordering against the other PDOs as well.
     pub fn set_pdo(&mut self, pdo: PdoChannel, data: &Pdo) -> Result<(), Error<E>> {
-        if let Pdo::Fixed { .. } = data {
-            self.write_word(
-                match pdo {
-                    PdoChannel::PDO1 => Register::DPMSNKPDO1,
-                    PdoChannel::PDO2 => Register::DPMSNKPDO2,
-                    PdoChannel::PDO3 => Register::DPMSNKPDO3,
-                },
-                data.bits(),
-            )
-        } else {
-            // Can only advertise fixed PDOs
-            Err(Error::InvalidPdo)
+        match validate_sink_pdo(pdo as usize, data).first() {
+            Some(Violation::NotFixed { .. }) => Err(Error::InvalidPdo(data.bits())),
+            Some(violation) => Err(Error::PdoViolation(*violation)),
+            None => self.write_word(pdo.register(), data.bits()),
         }
     }
 
     pub fn get_pdo(&mut self, pdo: PdoChannel) -> Result<Pdo, Error<E>> {
-        Pdo::from_bits(self.read_word(match pdo {
-            PdoChannel::PDO1 => Register::DPMSNKPDO1,
-            PdoChannel::PDO2 => Register::DPMSNKPDO2,
-            PdoChannel::PDO3 => Register::DPMSNKPDO3,
-        })?)
-        .ok_or(Error::InvalidPdo)
+        let bits = self.read_word(pdo.register())?;
+        Pdo::from_bits(bits).ok_or(Error::InvalidPdo(bits))
     }
 
     pub fn get_current_rdo(&mut self) -> Result<Rdo, Error<E>> {
-        Ok(Rdo(self.read_word(Register::RDORegStatus)?))
+        self.read()
     }
 
-    pub fn set_num_pdo(&mut self, num: u8) -> Result<(), Error<E>> {
-        match num {
-            1..=3 => self.write(Register::DPMPDONumb, num),
-            _ => Err(Error::OutaRangePdo),
+    /// State of the POWER_OK outputs, based on the active contract and the Type-C current
+    ///
+    /// `config` is the [`NvmConfig::power_ok_config`] the controller was started with.
+    pub fn get_power_ok_output(&mut self, config: PowerOkConfig) -> Result<PowerOkState, Error<E>> {
+        let rp = self.get_cc_status()?.rp_current();
+        let position = self.get_current_rdo()?.position() as u8;
+        Ok(config.asserted_output(position, rp))
+    }
+
+    /// Read the number of advertised sink PDOs and the PDOs themselves
+    pub fn get_sink_pdos(&mut self) -> Result<SinkPdos, Error<E>> {
+        let num = self.read::<DpmPdoNumb>()?.num();
+        if !(1..=3).contains(&num) {
+            return Err(Error::OutaRangePdo(num));
         }
+
+        let mut buf = [0x00; 12];
+        self.read_registers(Register::DPMSNKPDO1, Register::DPMSNKPDO3, &mut buf)?;
+        let mut pdos = [Pdo::new_fixed(0, 0); 3];
+        for (pdo, raw) in pdos.iter_mut().zip(buf.chunks_exact(4)).take(num as usize) {
+            let bits = LittleEndian::read_u32(raw);
+            *pdo = Pdo::from_bits(bits).ok_or(Error::InvalidPdo(bits))?;
+        }
+        Ok(SinkPdos::new(pdos, num as usize))
+    }
+
+    /// Write the sink PDOs and the number of PDOs to advertise
+    ///
+    /// The PDOs must pass [`validate_sink_pdos`], otherwise the first violation is returned. The
+    /// writes are ordered such that the controller never advertises a
+    /// mix of old and new PDOs that breaks these rules.
+    pub fn set_sink_pdos(&mut self, pdos: &[Pdo]) -> Result<(), Error<E>> {
+        if let Some(violation) = validate_sink_pdos(pdos).first() {
+            return Err(Error::PdoViolation(*violation));
+        }
+
+        self.write_sink_pdos(pdos)
+    }
+
+    /// Renegotiate with the sink PDOs derated to `limit`
+    ///
+    /// The PDOs are derated with [`derate_sink_pdos`] and a soft reset is sent. The policy
+    /// engine and the received messages are then polled every millisecond for up to a second,
+    /// and the new contract is checked against `limit` using the Source_Capabilities the source
+    /// resent. If those were missed, the contract counts as exceeding the limit. As this
+    /// consumes the received messages, a [`CapsTracker`](caps::CapsTracker) does not see them.
+    ///
+    /// If the PDOs are already within the limit, the current contract is returned without a
+    /// soft reset.
+    ///
+    /// If writing the PDOs or the renegotiation fails, or the contract exceeds the limit, the
+    /// previous PDOs are restored and renegotiated before returning the error. If restoring them
+    /// fails as well, [`Error::RollbackFailed`] holds both causes.
+    pub fn set_power_limit<D: DelayMs<u16>>(
+        &mut self,
+        limit: PowerLimit,
+        delay: &mut D,
+    ) -> Result<Rdo, Error<E>> {
+        let previous = self.get_sink_pdos()?;
+        let derated = derate_sink_pdos(&previous, limit).map_err(Error::PdoViolation)?;
+        if derated.as_slice() == previous.as_slice() {
+            return self.get_current_rdo();
+        }
+        if let Some(violation) = validate_sink_pdos(&derated).first() {
+            return Err(Error::PdoViolation(*violation));
+        }
+
+        let err = match self.apply_power_limit(limit, &derated, delay) {
+            Ok(rdo) => return Ok(rdo),
+            Err(err) => err,
+        };
+
+        // The previous PDOs were accepted by the chip before, write them back as they were
+        // instead of failing on a validation rule
+        let rollback = self
+            .write_sink_pdos(&previous)
+            .and_then(|()| self.renegotiate(delay));
+        let cause = match err {
+            _ if rollback.is_ok() => return Err(err),
+            Error::NegotiationTimeout => RenegotiationFailure::Timeout,
+            Error::LimitNotMet(rdo) => RenegotiationFailure::LimitNotMet(rdo),
+            Error::I2CError {
+                operation,
+                register,
+                error,
+            } => RenegotiationFailure::I2CError {
+                operation,
+                register,
+                error,
+            },
+            // Not returned by writing PDOs or renegotiating
+            err => return Err(err),
+        };
+        match rollback {
+            Ok(_) => Err(cause.into()),
+            Err(Error::I2CError { error, .. }) => Err(Error::RollbackFailed {
+                cause,
+                error: Some(error),
+            }),
+            Err(_) => Err(Error::RollbackFailed { cause, error: None }),
+        }
+    }
+
+    /// Write validated sink PDOs and renegotiate, checking the new contract against `limit`
+    fn apply_power_limit<D: DelayMs<u16>>(
+        &mut self,
+        limit: PowerLimit,
+        pdos: &[Pdo],
+        delay: &mut D,
+    ) -> Result<Rdo, Error<E>> {
+        self.write_sink_pdos(pdos)?;
+        let caps = self.renegotiate(delay)?;
+        let rdo = self.get_current_rdo()?;
+        if caps.is_some_and(|caps| limit.allows(&caps, &rdo)) {
+            Ok(rdo)
+        } else {
+            Err(Error::LimitNotMet(rdo))
+        }
+    }
+
+    /// Write sink PDOs without validating them
+    fn write_sink_pdos(&mut self, pdos: &[Pdo]) -> Result<(), Error<E>> {
+        let mut buf = [0x00; 12];
+        for (raw, pdo) in buf.chunks_exact_mut(4).zip(pdos) {
+            LittleEndian::write_u32(raw, pdo.bits());
+        }
+        let buf = &buf[..pdos.len() * 4];
+        let num = pdos.len() as u8;
+
+        // Any prefix of a valid PDO set is valid as well, so shrink the count before replacing
+        // the PDOs and grow it afterwards.
+        let current = self.read::<DpmPdoNumb>()?.num();
+        if num < current {
+            self.write(DpmPdoNumb::from_bits(num))?;
+            self.write_registers(Register::DPMSNKPDO1, buf)
+        } else {
+            self.write_registers(Register::DPMSNKPDO1, buf)?;
+            self.write(DpmPdoNumb::from_bits(num))
+        }
+    }
+
+    /// Soft reset and wait for the new contract, returning the capabilities the source resent
+    ///
+    /// The policy engine is still in a ready state right after the reset, so a contract only
+    /// counts once the engine was seen leaving the ready state or the RDO changed. Received
+    /// messages are read on every poll, before Accept and PS_RDY overwrite the capabilities.
+    fn renegotiate<D: DelayMs<u16>>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<Option<SourceCapabilities>, Error<E>> {
+        let previous = self.get_current_rdo()?.0;
+        self.soft_reset()?;
+
+        let mut caps = None;
+        let mut left_ready = false;
+        for _ in 0..1000 {
+            delay.delay_ms(1);
+            if let Some(message) = self.poll_rx_message()? {
+                caps = message.source_capabilities().or(caps);
+            }
+            if !self.get_pe_state()?.is_ready() {
+                left_ready = true;
+            } else if left_ready || self.get_current_rdo()?.0 != previous {
+                return Ok(caps);
+            }
+        }
+        Err(Error::NegotiationTimeout)
+    }
+
+    pub fn set_num_pdo(&mut self, num: u8) -> Result<(), Error<E>> {
+        let num = DpmPdoNumb::new(num).ok_or(Error::OutaRangePdo(num))?;
+        self.write(num)
+    }
+
+    /// Drive the GPIO pin low (`true`) or release it (`false`)
+    ///
+    /// Only has an effect when the pin is configured as [`GpioFunction::SwCtrlGpio`].
+    pub fn set_gpio(&mut self, low: bool) -> Result<(), Error<E>> {
+        self.write(if low {
+            GpioSWGpio::Gpio
+        } else {
+            GpioSWGpio::empty()
+        })
+    }
+
+    /// Whether the GPIO pin is driven low by software
+    pub fn get_gpio(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.read::<GpioSWGpio>()?.contains(GpioSWGpio::Gpio))
     }
 
     /// Unlock the NVM for reading and writing
-    pub fn unlock_nvm(&mut self) -> Result<STUSB4500Nvm<I2C>, Error<E>> {
-        STUSB4500Nvm::unlock(self)
+    pub fn unlock_nvm(&mut self) -> Result<STUSB4500Nvm<'_, I2C>, Error<E>> {
+        STUSB4500Nvm::unlock(self, DEFAULT_NVM_PASSWORD)
+    }
+
+    /// Unlock the NVM for reading and writing using a custom password
+    ///
+    /// Returns [`Error::NvmLocked`] if the controller did not accept the password.
+    pub fn unlock_nvm_with_password(
+        &mut self,
+        password: u8,
+    ) -> Result<STUSB4500Nvm<'_, I2C>, Error<E>> {
+        STUSB4500Nvm::unlock(self, password)
+    }
+
+    /// Read a typed register
+    pub fn read<R: Readable>(&mut self) -> Result<R, Error<E>> {
+        let mut buf = [0x00; 4];
+        let width = R::REGISTER.width();
+        self.read_raw(R::REGISTER, &mut buf[..width])?;
+        Ok(R::from_raw(LittleEndian::read_u32(&buf)))
+    }
+
+    /// Write a typed register
+    ///
+    /// Only registers implementing [`Writable`] can be written, read-only and read-clear
+    /// registers are rejected at compile time.
+    pub fn write<R: Writable>(&mut self, value: R) -> Result<(), Error<E>> {
+        const {
+            assert!(
+                matches!(R::ACCESS, Access::ReadWrite)
+                    && matches!(R::REGISTER.access(), Access::ReadWrite),
+                "Writable implemented for a register that is not read-write"
+            )
+        };
+        let mut buf = [0x00; 4];
+        LittleEndian::write_u32(&mut buf, value.into_raw());
+        self.write_raw(R::REGISTER, &buf[..R::REGISTER.width()])
+    }
+
+    /// Read, modify and write back a typed register
+    pub fn modify<R: Writable>(&mut self, f: impl FnOnce(R) -> R) -> Result<(), Error<E>> {
+        let value = self.read::<R>()?;
+        self.write(f(value))
+    }
+
+    /// Read the registers from `first` to `last` (inclusive) in a single transaction
+    ///
+    /// Reserved addresses within the range are read as well. `buf` must be at least as large as
+    /// the range, the filled part is returned.
+    pub fn read_registers<'b>(
+        &mut self,
+        first: Register,
+        last: Register,
+        buf: &'b mut [u8],
+    ) -> Result<&'b [u8], Error<E>> {
+        let len = Self::range_len(first, last)?;
+        if buf.len() < len {
+            return Err(Error::InvalidRange {
+                start: first,
+                len: buf.len(),
+            });
+        }
+        let buf = &mut buf[..len];
+        self.read_raw(first, buf)?;
+        Ok(buf)
+    }
+
+    /// Write consecutive registers starting at `start` in a single transaction
+    ///
+    /// The range may only include read-write registers, no reserved addresses, must end on a
+    /// register boundary and may be at most [`MAX_WRITE_LEN`] bytes long.
+    pub fn write_registers(&mut self, start: Register, data: &[u8]) -> Result<(), Error<E>> {
+        let invalid = Error::InvalidRange {
+            start,
+            len: data.len(),
+        };
+        if data.is_empty() || data.len() > MAX_WRITE_LEN {
+            return Err(invalid);
+        }
+        let mut address = start.address() as usize;
+        let end = address + data.len();
+        while address < end {
+            match Register::containing(address as u8) {
+                Some(register)
+                    if register.address() as usize == address
+                        && register.access() == Access::ReadWrite =>
+                {
+                    address += register.width()
+                }
+                _ => return Err(invalid),
+            }
+        }
+        if address != end {
+            return Err(invalid);
+        }
+        self.write_raw(start, data)
+    }
+
+    fn range_len(first: Register, last: Register) -> Result<usize, Error<E>> {
+        let end = last.address() as usize + last.width();
+        match end.checked_sub(first.address() as usize) {
+            Some(len) if len > 0 => Ok(len),
+            _ => Err(Error::InvalidRange {
+                start: first,
+                len: 0,
+            }),
+        }
     }
 
     // *****************************************************************
     // Raw access functions
 
     /// Write a byte register
-    pub(crate) fn write(&mut self, register: Register, value: u8) -> Result<(), Error<E>> {
-        let buf = [register as u8, value];
-        self.i2c
-            .write(self.address, &buf)
-            .map_err(|err| Error::I2CError(err))
+    pub(crate) fn write_byte(&mut self, register: Register, value: u8) -> Result<(), Error<E>> {
+        self.write_raw(register, &[value])
     }
 
     /// Write a word register
     pub(crate) fn write_word(&mut self, register: Register, word: u32) -> Result<(), Error<E>> {
-        let mut buf = [0x00; 5];
-        buf[0] = register as u8;
-        LittleEndian::write_u32(&mut buf[1..], word);
-        self.i2c
-            .write(self.address, &buf)
-            .map_err(|err| Error::I2CError(err))
+        let mut buf = [0x00; 4];
+        LittleEndian::write_u32(&mut buf, word);
+        self.write_raw(register, &buf)
     }
 
     /// Read a byte register
-    pub(crate) fn read(&mut self, register: Register) -> Result<u8, Error<E>> {
+    pub(crate) fn read_byte(&mut self, register: Register) -> Result<u8, Error<E>> {
         let mut buf = [0x00; 1];
-        self.i2c
-            .write(self.address, &[register as u8])
-            .map_err(|err| Error::I2CError(err))?;
-        self.i2c
-            .read(self.address, &mut buf)
-            .map_err(|err| Error::I2CError(err))?;
+        self.read_raw(register, &mut buf)?;
         Ok(buf[0])
     }
 
     /// Read a word register
     pub(crate) fn read_word(&mut self, register: Register) -> Result<u32, Error<E>> {
         let mut buf = [0x00; 4];
+        self.read_raw(register, &mut buf)?;
+        Ok(LittleEndian::read_u32(&buf))
+    }
+
+    /// Write consecutive bytes starting at a register, at most [`MAX_WRITE_LEN`] bytes
+    pub(crate) fn write_raw(&mut self, register: Register, data: &[u8]) -> Result<(), Error<E>> {
+        trace(Operation::Write, register, data);
+        let mut buf = [0x00; MAX_WRITE_LEN + 1];
+        buf[0] = register as u8;
+        buf[1..=data.len()].copy_from_slice(data);
         self.i2c
-            .write(self.address, &[register as u8])
-            .map_err(|err| Error::I2CError(err))?;
+            .write(self.address, &buf[..=data.len()])
+            .map_err(|error| Error::I2CError {
+                operation: Operation::Write,
+                register,
+                error,
+            })
+    }
+
+    /// Read consecutive bytes starting at a register
+    pub(crate) fn read_raw(&mut self, register: Register, buf: &mut [u8]) -> Result<(), Error<E>> {
         self.i2c
-            .read(self.address, &mut buf)
-            .map_err(|err| Error::I2CError(err))?;
-        Ok(LittleEndian::read_u32(&buf))
+            .write(self.address, &[register as u8])
+            .and_then(|_| self.i2c.read(self.address, buf))
+            .map_err(|error| Error::I2CError {
+                operation: Operation::Read,
+                register,
+                error,
+            })?;
+        trace(Operation::Read, register, buf);
+        Ok(())
     }
 }
 
+/// Trace a register access through `defmt` and/or `log`
+#[allow(unused_variables)]
+fn trace(operation: Operation, register: Register, data: &[u8]) {
+    #[cfg(feature = "defmt")]
+    defmt::trace!("{} {} = {=[u8]:02X}", operation, register, data);
+    #[cfg(feature = "log")]
+    log::trace!("{:?} {:?} = {:02X?}", operation, register, data);
+}
+
 pub struct STUSB4500Nvm<'a, I2C> {
     inner: &'a mut STUSB4500<I2C>,
 }
@@ -229,25 +720,34 @@ impl<I2C, E> STUSB4500Nvm<'_, I2C>
 where
     I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
 {
-    const DEFAULT_PASSWORD: u8 = 0x47;
-
-    pub(crate) fn unlock(inner: &mut STUSB4500<I2C>) -> Result<STUSB4500Nvm<I2C>, Error<E>> {
-        inner.write(Register::NvmPassword, STUSB4500Nvm::<I2C>::DEFAULT_PASSWORD)?;
-        inner.write(Register::NvmCtrl0, 0x00)?;
-        inner.write(
+    pub(crate) fn unlock(
+        inner: &mut STUSB4500<I2C>,
+        password: u8,
+    ) -> Result<STUSB4500Nvm<'_, I2C>, Error<E>> {
+        inner.write_byte(Register::NvmPassword, password)?;
+        inner.write_byte(Register::NvmCtrl0, 0x00)?;
+        inner.write_byte(
             Register::NvmCtrl0,
             (NvmCtrl0::Power | NvmCtrl0::Enable).bits(),
         )?;
 
+        // NvmCtrl0 ignores writes while the password is wrong, so reading it back tells us
+        // whether the unlock took effect.
+        let ctrl = NvmCtrl0::from_bits_truncate(inner.read_byte(Register::NvmCtrl0)?);
+        if !ctrl.contains(NvmCtrl0::Power | NvmCtrl0::Enable) {
+            inner.write_byte(Register::NvmPassword, 0x00)?;
+            return Err(Error::NvmLocked);
+        }
+
         Ok(STUSB4500Nvm { inner })
     }
 
     /// Lock the NVM
     pub fn lock(self) -> Result<(), Error<E>> {
         self.inner
-            .write(Register::NvmCtrl0, NvmCtrl0::Enable.bits())?;
-        self.inner.write(Register::NvmCtrl1, 0x00)?;
-        self.inner.write(Register::NvmPassword, 0x00)
+            .write_byte(Register::NvmCtrl0, NvmCtrl0::Enable.bits())?;
+        self.inner.write_byte(Register::NvmCtrl1, 0x00)?;
+        self.inner.write_byte(Register::NvmPassword, 0x00)
     }
 
     /// Read the NVM data (all five sectors)
@@ -278,17 +778,27 @@ where
         Ok(())
     }
 
+    /// Read and decode the NVM configuration
+    pub fn read_config(&mut self) -> Result<NvmConfig, Error<E>> {
+        Ok(NvmConfig::from_sectors(self.read_sectors()?))
+    }
+
+    /// Write a NVM configuration
+    pub fn write_config(&mut self, config: &NvmConfig) -> Result<(), Error<E>> {
+        self.write_sectors(config.sectors())
+    }
+
     fn issue_request(&mut self) -> Result<(), Error<E>> {
         self.issue_request_with_sector(0)
     }
 
     fn issue_request_with_sector(&mut self, sector: u8) -> Result<(), Error<E>> {
-        self.inner.write(
+        self.inner.write_byte(
             Register::NvmCtrl0,
             sector | (NvmCtrl0::Power | NvmCtrl0::Enable | NvmCtrl0::Request).bits(),
         )?;
 
-        while NvmCtrl0::from_bits_truncate(self.inner.read(Register::NvmCtrl0)?)
+        while NvmCtrl0::from_bits_truncate(self.inner.read_byte(Register::NvmCtrl0)?)
             .contains(NvmCtrl0::Request)
         {}
 
@@ -296,42 +806,39 @@ where
     }
 
     fn read_sector(&mut self, sector: u8) -> Result<[u8; 8], Error<E>> {
-        self.inner
-            .write(Register::NvmCtrl1, NvmCtrl1Opcode::ReadSector as u8)?;
-        self.issue_request_with_sector(sector)?;
-
+        let opcode = NvmCtrl1Opcode::ReadSector;
         let mut buf = [0x00; 8];
         self.inner
-            .i2c
-            .write(self.inner.address, &[Register::RWBuffer as u8])
-            .map_err(|err| Error::I2CError(err))?;
-        self.inner
-            .i2c
-            .read(self.inner.address, &mut buf)
-            .map_err(|err| Error::I2CError(err))?;
+            .write_byte(Register::NvmCtrl1, opcode as u8)
+            .and_then(|_| self.issue_request_with_sector(sector))
+            .and_then(|_| self.inner.read_raw(Register::RWBuffer, &mut buf))
+            .map_err(|err| err.in_nvm(sector, opcode))?;
         Ok(buf)
     }
 
     fn write_sector(&mut self, sector: u8, data: &[u8; 8]) -> Result<(), Error<E>> {
-        let mut buf = [0x00; 9];
-        buf[0] = Register::RWBuffer as u8;
-        buf[1..].copy_from_slice(data);
-
-        self.inner
-            .i2c
-            .write(self.inner.address, &buf)
-            .map_err(|err| Error::I2CError(err))?;
         self.inner
-            .write(Register::NvmCtrl1, NvmCtrl1Opcode::LoadPlr as u8)?;
-        self.issue_request()?;
+            .write_raw(Register::RWBuffer, data)
+            .and_then(|_| {
+                self.inner
+                    .write_byte(Register::NvmCtrl1, NvmCtrl1Opcode::LoadPlr as u8)
+            })
+            .and_then(|_| self.issue_request())
+            .map_err(|err| err.in_nvm(sector, NvmCtrl1Opcode::LoadPlr))?;
 
         self.inner
-            .write(Register::NvmCtrl1, NvmCtrl1Opcode::WriteSector as u8)?;
-        self.issue_request_with_sector(sector)
+            .write_byte(Register::NvmCtrl1, NvmCtrl1Opcode::WriteSector as u8)
+            .and_then(|_| self.issue_request_with_sector(sector))
+            .map_err(|err| err.in_nvm(sector, NvmCtrl1Opcode::WriteSector))
     }
 
     fn erase_sectors(&mut self) -> Result<(), Error<E>> {
-        self.inner.write(
+        self.erase_sectors_inner()
+            .map_err(|err| err.in_nvm(0, NvmCtrl1Opcode::EraseSectors))
+    }
+
+    fn erase_sectors_inner(&mut self) -> Result<(), Error<E>> {
+        self.inner.write_byte(
             Register::NvmCtrl1,
             NvmCtrl1Opcode::LoadSer as u8
                 | (NvmCtrl1::EraseSector0
@@ -344,7 +851,7 @@ where
         self.issue_request()?;
 
         self.inner
-            .write(Register::NvmCtrl1, NvmCtrl1Opcode::EraseSectors as u8)?;
+            .write_byte(Register::NvmCtrl1, NvmCtrl1Opcode::EraseSectors as u8)?;
         self.issue_request()
     }
 }
@@ -0,0 +1,226 @@
+//! USB-PD messages
+//!
+//! The STUSB4500 exposes the last received message in the `RX_HEADER` and `RX_DATA_OBJ`
+//! registers, see [`STUSB4500::get_rx_message`](crate::STUSB4500::get_rx_message).
+
+use core::fmt;
+
+use bitfield::bitfield;
+
+use crate::pdo::SourceCapabilities;
+
+bitfield! {
+    /// USB-PD message header
+    #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub struct Header(u16);
+    impl Debug;
+    pub extended, set_extended: 15;
+    pub u8, num_data_objects, set_num_data_objects: 14, 12;
+    pub u8, message_id, set_message_id: 11, 9;
+    pub port_power_role, set_port_power_role: 8;
+    pub u8, spec_revision, set_spec_revision: 7, 6;
+    pub port_data_role, set_port_data_role: 5;
+    pub u8, message_type, set_message_type: 4, 0;
+}
+
+impl Header {
+    /// Type of the message, control messages have no data objects
+    pub fn kind(&self) -> MessageKind {
+        match self.num_data_objects() {
+            _ if self.extended() => MessageKind::Extended(self.message_type()),
+            0 => MessageKind::Control(ControlMessage::from_bits(self.message_type())),
+            _ => MessageKind::Data(DataMessage::from_bits(self.message_type())),
+        }
+    }
+}
+
+/// Control message types
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControlMessage {
+    GoodCrc,
+    GotoMin,
+    Accept,
+    Reject,
+    Ping,
+    PsRdy,
+    GetSourceCap,
+    GetSinkCap,
+    DrSwap,
+    PrSwap,
+    VconnSwap,
+    Wait,
+    SoftReset,
+    NotSupported,
+    Other(u8),
+}
+
+impl ControlMessage {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0x01 => ControlMessage::GoodCrc,
+            0x02 => ControlMessage::GotoMin,
+            0x03 => ControlMessage::Accept,
+            0x04 => ControlMessage::Reject,
+            0x05 => ControlMessage::Ping,
+            0x06 => ControlMessage::PsRdy,
+            0x07 => ControlMessage::GetSourceCap,
+            0x08 => ControlMessage::GetSinkCap,
+            0x09 => ControlMessage::DrSwap,
+            0x0A => ControlMessage::PrSwap,
+            0x0B => ControlMessage::VconnSwap,
+            0x0C => ControlMessage::Wait,
+            0x0D => ControlMessage::SoftReset,
+            0x10 => ControlMessage::NotSupported,
+            other => ControlMessage::Other(other),
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            ControlMessage::GoodCrc => 0x01,
+            ControlMessage::GotoMin => 0x02,
+            ControlMessage::Accept => 0x03,
+            ControlMessage::Reject => 0x04,
+            ControlMessage::Ping => 0x05,
+            ControlMessage::PsRdy => 0x06,
+            ControlMessage::GetSourceCap => 0x07,
+            ControlMessage::GetSinkCap => 0x08,
+            ControlMessage::DrSwap => 0x09,
+            ControlMessage::PrSwap => 0x0A,
+            ControlMessage::VconnSwap => 0x0B,
+            ControlMessage::Wait => 0x0C,
+            ControlMessage::SoftReset => 0x0D,
+            ControlMessage::NotSupported => 0x10,
+            ControlMessage::Other(other) => other,
+        }
+    }
+}
+
+/// Data message types
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataMessage {
+    SourceCapabilities,
+    Request,
+    Bist,
+    SinkCapabilities,
+    VendorDefined,
+    Other(u8),
+}
+
+impl DataMessage {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0x01 => DataMessage::SourceCapabilities,
+            0x02 => DataMessage::Request,
+            0x03 => DataMessage::Bist,
+            0x04 => DataMessage::SinkCapabilities,
+            0x0F => DataMessage::VendorDefined,
+            other => DataMessage::Other(other),
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            DataMessage::SourceCapabilities => 0x01,
+            DataMessage::Request => 0x02,
+            DataMessage::Bist => 0x03,
+            DataMessage::SinkCapabilities => 0x04,
+            DataMessage::VendorDefined => 0x0F,
+            DataMessage::Other(other) => other,
+        }
+    }
+}
+
+/// Decoded message type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MessageKind {
+    Control(ControlMessage),
+    Data(DataMessage),
+    /// Extended message, with the raw message type
+    Extended(u8),
+}
+
+impl fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageKind::Control(ControlMessage::Other(t)) => write!(f, "Control(0x{:02X})", t),
+            MessageKind::Control(message) => write!(f, "{:?}", message),
+            MessageKind::Data(DataMessage::Other(t)) => write!(f, "Data(0x{:02X})", t),
+            MessageKind::Data(message) => write!(f, "{:?}", message),
+            MessageKind::Extended(t) => write!(f, "Extended(0x{:02X})", t),
+        }
+    }
+}
+
+/// Command written to `PD_COMMAND_CTRL`
+///
+/// `tx_header` is written to `TX_HEADER_L` first and selects the message to send.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PdCommand {
+    pub tx_header: u8,
+    pub command: u8,
+}
+
+impl PdCommand {
+    /// Send a Soft_Reset message, which triggers a new negotiation
+    pub const SOFT_RESET: PdCommand = PdCommand {
+        tx_header: 0x0D,
+        command: 0x26,
+    };
+
+    /// Control message selected by the TX header
+    pub fn message(&self) -> ControlMessage {
+        ControlMessage::from_bits(self.tx_header & 0x1F)
+    }
+}
+
+/// Maximum number of data objects in a message
+pub const MAX_DATA_OBJECTS: usize = 7;
+
+/// USB-PD message with its data objects
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Message {
+    pub header: Header,
+    objects: [u32; MAX_DATA_OBJECTS],
+}
+
+impl Message {
+    /// Create a message, the number of data objects in the header is set from `objects`
+    ///
+    /// At most [`MAX_DATA_OBJECTS`] objects are used.
+    pub fn new(mut header: Header, objects: &[u32]) -> Self {
+        let len = objects.len().min(MAX_DATA_OBJECTS);
+        header.set_num_data_objects(len as u8);
+        let mut message = Message {
+            header,
+            objects: [0; MAX_DATA_OBJECTS],
+        };
+        message.objects[..len].copy_from_slice(&objects[..len]);
+        message
+    }
+
+    pub fn kind(&self) -> MessageKind {
+        self.header.kind()
+    }
+
+    pub fn data_objects(&self) -> &[u32] {
+        let len = (self.header.num_data_objects() as usize).min(MAX_DATA_OBJECTS);
+        &self.objects[..len]
+    }
+
+    /// Decoded capabilities if this is a Source_Capabilities message
+    pub fn source_capabilities(&self) -> Option<SourceCapabilities> {
+        match self.kind() {
+            MessageKind::Data(DataMessage::SourceCapabilities) => {
+                SourceCapabilities::from_data_objects(self.data_objects())
+            }
+            _ => None,
+        }
+    }
+}
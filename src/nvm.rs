@@ -0,0 +1,510 @@
+//! Typed view of the NVM sectors
+//!
+//! The layout follows the NVM map used by the [GUI application][gui]. Only the fields that are
+//! understood are exposed, everything else is preserved as-is when editing a configuration.
+//!
+//! [gui]: https://www.st.com/en/embedded-software/stsw-stusb002.html
+
+use core::fmt;
+
+use crate::pdo::{Pdo, SinkPdos};
+use crate::registers::{RpCurrent, VoltageTolerance};
+use crate::PdoChannel;
+
+/// Errors raised when editing an [`NvmConfig`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// PDO1 is fixed at 5 V and its voltage cannot be changed
+    FixedPdo1,
+    /// Voltage outside of 5 V to 20 V or not a multiple of 50 mV
+    InvalidVoltage(u16),
+    /// Current that can not be represented in the NVM encoding
+    InvalidCurrent(u16),
+    /// Number of PDOs outside of 1 to 3
+    InvalidPdoCount(u8),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::FixedPdo1 => write!(f, "PDO1 is fixed at 5 V"),
+            ConfigError::InvalidVoltage(mv) => write!(f, "invalid PDO voltage: {} mV", mv),
+            ConfigError::InvalidCurrent(ma) => write!(f, "invalid PDO current: {} mA", ma),
+            ConfigError::InvalidPdoCount(num) => write!(f, "number of PDOs out of range: {}", num),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigError {}
+
+/// Function of the GPIO pin (`GPIO_CFG`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GpioFunction {
+    /// Controlled by software through the `GPIO_SW_GPIO` register
+    SwCtrlGpio = 0,
+    /// Asserted low while the controller is in hardware fault error recovery
+    ErrorRecovery = 1,
+    /// Asserted low when a debug accessory is attached
+    Debug = 2,
+    /// Asserted low while the sink is powered (`SINK_POWER`)
+    SinkPower = 3,
+}
+
+impl GpioFunction {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => GpioFunction::SwCtrlGpio,
+            1 => GpioFunction::ErrorRecovery,
+            2 => GpioFunction::Debug,
+            _ => GpioFunction::SinkPower,
+        }
+    }
+}
+
+/// Behaviour of the POWER_OK2 and POWER_OK3 pins (`POWER_OK_CFG`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerOkConfig {
+    /// POWER_OK2 is asserted for a contract above 5 V, i.e. on PDO2 or PDO3
+    Config1,
+    /// Raw value 1, documented as not applicable
+    NotApplicable,
+    /// POWER_OK2 and POWER_OK3 are asserted when PDO2 respectively PDO3 is negotiated
+    Config2,
+    /// POWER_OK2 and POWER_OK3 are asserted when the source advertises 1.5 A respectively 3 A
+    /// through its Rp termination
+    Config3,
+}
+
+impl PowerOkConfig {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => PowerOkConfig::Config1,
+            1 => PowerOkConfig::NotApplicable,
+            2 => PowerOkConfig::Config2,
+            _ => PowerOkConfig::Config3,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            PowerOkConfig::Config1 => 0,
+            PowerOkConfig::NotApplicable => 1,
+            PowerOkConfig::Config2 => 2,
+            PowerOkConfig::Config3 => 3,
+        }
+    }
+
+    /// State of the POWER_OK outputs
+    ///
+    /// `position` is the sink PDO of the active contract, 0 without a contract, and `rp` the
+    /// current advertised by the source, `None` if nothing is attached. Returns
+    /// [`PowerOkState::Unknown`] for [`PowerOkConfig::NotApplicable`].
+    pub fn asserted_output(self, position: u8, rp: Option<RpCurrent>) -> PowerOkState {
+        let rp = match rp {
+            Some(rp) => rp,
+            None => return PowerOkState::Released,
+        };
+        match (self, position, rp) {
+            (PowerOkConfig::Config1, 2 | 3, _) => PowerOkState::Asserted(PowerOkOutput::PowerOk2),
+            (PowerOkConfig::Config2, 2, _) => PowerOkState::Asserted(PowerOkOutput::PowerOk2),
+            (PowerOkConfig::Config2, 3, _) => PowerOkState::Asserted(PowerOkOutput::PowerOk3),
+            (PowerOkConfig::Config3, _, RpCurrent::Current1A5) => {
+                PowerOkState::Asserted(PowerOkOutput::PowerOk2)
+            }
+            (PowerOkConfig::Config3, _, RpCurrent::Current3A0) => {
+                PowerOkState::Asserted(PowerOkOutput::PowerOk3)
+            }
+            (PowerOkConfig::NotApplicable, _, _) => PowerOkState::Unknown,
+            _ => PowerOkState::Released,
+        }
+    }
+}
+
+/// POWER_OK output pin
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerOkOutput {
+    PowerOk2,
+    PowerOk3,
+}
+
+/// State of the POWER_OK2 and POWER_OK3 outputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerOkState {
+    /// Neither output is asserted
+    Released,
+    Asserted(PowerOkOutput),
+    /// The configuration is not applicable
+    Unknown,
+}
+
+/// Sink PDO current as stored in the NVM (`I_SNK_PDOx`)
+///
+/// The NVM stores a 4-bit index into a current table rather than the 10 mA units used by
+/// [`FixedPdo`](crate::pdo::FixedPdo). Index 0 selects the `FLEX_I` current instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NvmCurrent(u8);
+
+impl NvmCurrent {
+    /// Use the `FLEX_I` current
+    pub const FLEX: NvmCurrent = NvmCurrent(0);
+
+    /// Create from the raw 4-bit encoding
+    pub fn from_bits(bits: u8) -> Self {
+        NvmCurrent(bits & 0x0F)
+    }
+
+    /// Raw 4-bit encoding
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Encode a current in mA
+    ///
+    /// Only the steps of the current table are accepted: 0.5 A to 3 A in 250 mA steps and
+    /// 3 A to 5 A in 500 mA steps.
+    pub fn from_milliamps(current: u16) -> Option<Self> {
+        let index = match current {
+            500..=3000 if current.is_multiple_of(250) => current / 250 - 1,
+            3500..=5000 if current.is_multiple_of(500) => current / 500 + 5,
+            _ => return None,
+        };
+        Some(NvmCurrent(index as u8))
+    }
+
+    /// Whether the `FLEX_I` current is used
+    pub fn is_flex(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Current in mA, or `None` if the `FLEX_I` current is used
+    pub fn milliamps(self) -> Option<u16> {
+        match self.0 {
+            0 => None,
+            1..=10 => Some(self.0 as u16 * 250 + 250),
+            _ => Some(self.0 as u16 * 500 - 2500),
+        }
+    }
+
+    /// Current in mA, using `flex_current` in place of the `FLEX_I` setting
+    pub fn resolve(self, flex_current: u16) -> u16 {
+        self.milliamps().unwrap_or(flex_current)
+    }
+}
+
+/// Decoded NVM configuration
+///
+/// Created from the raw sectors returned by
+/// [`STUSB4500Nvm::read_sectors`](crate::STUSB4500Nvm::read_sectors) and turned back into sectors
+/// for [`STUSB4500Nvm::write_sectors`](crate::STUSB4500Nvm::write_sectors).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NvmConfig {
+    sectors: [[u8; 8]; 5],
+}
+
+impl NvmConfig {
+    pub fn from_sectors(sectors: [[u8; 8]; 5]) -> Self {
+        NvmConfig { sectors }
+    }
+
+    pub fn sectors(&self) -> [[u8; 8]; 5] {
+        self.sectors
+    }
+
+    /// Number of advertised sink PDOs (`DPM_SNK_PDO_NUMB`)
+    pub fn num_pdo(&self) -> u8 {
+        (self.sectors[3][2] & 0x06) >> 1
+    }
+
+    pub fn set_num_pdo(&mut self, num: u8) -> Result<(), ConfigError> {
+        match num {
+            1..=3 => {
+                self.sectors[3][2] = (self.sectors[3][2] & !0x06) | (num << 1);
+                Ok(())
+            }
+            _ => Err(ConfigError::InvalidPdoCount(num)),
+        }
+    }
+
+    /// Voltage of a sink PDO in mV
+    pub fn pdo_voltage(&self, pdo: PdoChannel) -> u16 {
+        let units = match pdo {
+            PdoChannel::PDO1 => return 5000,
+            PdoChannel::PDO2 => (self.sectors[4][1] as u16) << 2 | (self.sectors[4][0] as u16) >> 6,
+            PdoChannel::PDO3 => (self.sectors[4][3] as u16 & 0x03) << 8 | self.sectors[4][2] as u16,
+        };
+        units * 50
+    }
+
+    /// Set the voltage of PDO2 or PDO3 in mV
+    pub fn set_pdo_voltage(&mut self, pdo: PdoChannel, voltage: u16) -> Result<(), ConfigError> {
+        if !(5000..=20000).contains(&voltage) || !voltage.is_multiple_of(50) {
+            return Err(ConfigError::InvalidVoltage(voltage));
+        }
+        let units = voltage / 50;
+        match pdo {
+            PdoChannel::PDO1 => return Err(ConfigError::FixedPdo1),
+            PdoChannel::PDO2 => {
+                self.sectors[4][0] = (self.sectors[4][0] & 0x3F) | ((units as u8 & 0x03) << 6);
+                self.sectors[4][1] = (units >> 2) as u8;
+            }
+            PdoChannel::PDO3 => {
+                self.sectors[4][2] = units as u8;
+                self.sectors[4][3] = (self.sectors[4][3] & 0xFC) | (units >> 8) as u8;
+            }
+        }
+        Ok(())
+    }
+
+    /// Current setting of a sink PDO
+    pub fn pdo_current(&self, pdo: PdoChannel) -> NvmCurrent {
+        NvmCurrent::from_bits(match pdo {
+            PdoChannel::PDO1 => self.sectors[3][2] >> 4,
+            PdoChannel::PDO2 => self.sectors[3][4],
+            PdoChannel::PDO3 => self.sectors[3][5] >> 4,
+        })
+    }
+
+    pub fn set_pdo_current(&mut self, pdo: PdoChannel, current: NvmCurrent) {
+        let bits = current.bits();
+        match pdo {
+            PdoChannel::PDO1 => self.sectors[3][2] = (self.sectors[3][2] & 0x0F) | bits << 4,
+            PdoChannel::PDO2 => self.sectors[3][4] = (self.sectors[3][4] & 0xF0) | bits,
+            PdoChannel::PDO3 => self.sectors[3][5] = (self.sectors[3][5] & 0x0F) | bits << 4,
+        }
+    }
+
+    /// Current of a sink PDO in mA, with `FLEX_I` resolved
+    pub fn pdo_current_milliamps(&self, pdo: PdoChannel) -> u16 {
+        self.pdo_current(pdo).resolve(self.flex_current())
+    }
+
+    /// Flexible current (`FLEX_I`) in mA
+    pub fn flex_current(&self) -> u16 {
+        let units = (self.sectors[4][4] as u16 & 0x0F) << 6 | (self.sectors[4][3] as u16) >> 2;
+        units * 10
+    }
+
+    /// Set the flexible current (`FLEX_I`) in mA, up to 5 A in 10 mA steps
+    pub fn set_flex_current(&mut self, current: u16) -> Result<(), ConfigError> {
+        if current > 5000 || !current.is_multiple_of(10) {
+            return Err(ConfigError::InvalidCurrent(current));
+        }
+        let units = current / 10;
+        self.sectors[4][3] = (self.sectors[4][3] & 0x03) | ((units as u8 & 0x3F) << 2);
+        self.sectors[4][4] = (self.sectors[4][4] & 0xF0) | (units >> 6) as u8;
+        Ok(())
+    }
+
+    /// Over-voltage tolerance of a sink PDO (`SNK_HLx`)
+    pub fn upper_tolerance(&self, pdo: PdoChannel) -> VoltageTolerance {
+        VoltageTolerance::from_bits(match pdo {
+            PdoChannel::PDO1 => self.sectors[3][3] >> 4,
+            PdoChannel::PDO2 => self.sectors[3][5],
+            PdoChannel::PDO3 => self.sectors[3][6],
+        })
+    }
+
+    pub fn set_upper_tolerance(&mut self, pdo: PdoChannel, tolerance: VoltageTolerance) {
+        let bits = tolerance.bits();
+        match pdo {
+            PdoChannel::PDO1 => self.sectors[3][3] = (self.sectors[3][3] & 0x0F) | bits << 4,
+            PdoChannel::PDO2 => self.sectors[3][5] = (self.sectors[3][5] & 0xF0) | bits,
+            PdoChannel::PDO3 => self.sectors[3][6] = (self.sectors[3][6] & 0xF0) | bits,
+        }
+    }
+
+    /// Under-voltage tolerance of a sink PDO (`SNK_LLx`)
+    pub fn lower_tolerance(&self, pdo: PdoChannel) -> VoltageTolerance {
+        VoltageTolerance::from_bits(match pdo {
+            PdoChannel::PDO1 => self.sectors[3][3],
+            PdoChannel::PDO2 => self.sectors[3][4] >> 4,
+            PdoChannel::PDO3 => self.sectors[3][6] >> 4,
+        })
+    }
+
+    pub fn set_lower_tolerance(&mut self, pdo: PdoChannel, tolerance: VoltageTolerance) {
+        let bits = tolerance.bits();
+        match pdo {
+            PdoChannel::PDO1 => self.sectors[3][3] = (self.sectors[3][3] & 0xF0) | bits,
+            PdoChannel::PDO2 => self.sectors[3][4] = (self.sectors[3][4] & 0x0F) | bits << 4,
+            PdoChannel::PDO3 => self.sectors[3][6] = (self.sectors[3][6] & 0x0F) | bits << 4,
+        }
+    }
+
+    /// Function of the GPIO pin
+    pub fn gpio_function(&self) -> GpioFunction {
+        GpioFunction::from_bits(self.sectors[1][0] >> 4)
+    }
+
+    pub fn set_gpio_function(&mut self, function: GpioFunction) {
+        self.sectors[1][0] = (self.sectors[1][0] & 0xCF) | (function as u8) << 4;
+    }
+
+    /// Behaviour of the POWER_OK2 and POWER_OK3 pins
+    pub fn power_ok_config(&self) -> PowerOkConfig {
+        PowerOkConfig::from_bits(self.sectors[4][4] >> 5)
+    }
+
+    pub fn set_power_ok_config(&mut self, config: PowerOkConfig) {
+        self.sectors[4][4] = (self.sectors[4][4] & 0x9F) | config.bits() << 5;
+    }
+
+    /// Whether VBUS_EN_SNK is only asserted for contracts above 5 V (`POWER_ONLY_ABOVE_5V`)
+    pub fn power_only_above_5v(&self) -> bool {
+        self.sectors[4][6] & 0x08 != 0
+    }
+
+    pub fn set_power_only_above_5v(&mut self, enable: bool) {
+        self.sectors[4][6] = (self.sectors[4][6] & !0x08) | (enable as u8) << 3;
+    }
+
+    /// Sink PDOs advertised with this configuration
+    ///
+    /// Can be checked with [`validate_sink_pdos`](crate::pdo::validate_sink_pdos) before writing
+    /// the configuration.
+    pub fn sink_pdos(&self) -> SinkPdos {
+        let mut pdos = [Pdo::new_fixed(0, 0); 3];
+        for (pdo, channel) in
+            pdos.iter_mut()
+                .zip([PdoChannel::PDO1, PdoChannel::PDO2, PdoChannel::PDO3])
+        {
+            *pdo = Pdo::new_fixed(
+                self.pdo_voltage(channel) / 50,
+                self.pdo_current_milliamps(channel) / 10,
+            );
+        }
+        SinkPdos::new(pdos, (self.num_pdo() as usize).min(3))
+    }
+
+    /// Set voltage (mV) and current (mA) of a sink PDO
+    ///
+    /// The voltage of PDO1 is fixed at 5 V and must be given as such.
+    pub fn set_pdo(
+        &mut self,
+        pdo: PdoChannel,
+        voltage: u16,
+        current: u16,
+    ) -> Result<(), ConfigError> {
+        let current =
+            NvmCurrent::from_milliamps(current).ok_or(ConfigError::InvalidCurrent(current))?;
+        match pdo {
+            PdoChannel::PDO1 if voltage != 5000 => return Err(ConfigError::FixedPdo1),
+            PdoChannel::PDO1 => {}
+            _ => self.set_pdo_voltage(pdo, voltage)?,
+        }
+        self.set_pdo_current(pdo, current);
+        Ok(())
+    }
+}
+
+impl From<[[u8; 8]; 5]> for NvmConfig {
+    fn from(sectors: [[u8; 8]; 5]) -> Self {
+        NvmConfig::from_sectors(sectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::DEFAULT_NVM;
+
+    #[test]
+    fn default_nvm_pdos() {
+        let config = NvmConfig::from_sectors(DEFAULT_NVM);
+        assert_eq!(config.num_pdo(), 3);
+        assert_eq!(config.pdo_voltage(PdoChannel::PDO1), 5000);
+        assert_eq!(config.pdo_current_milliamps(PdoChannel::PDO1), 1500);
+        assert_eq!(config.pdo_voltage(PdoChannel::PDO2), 15000);
+        assert_eq!(config.pdo_current_milliamps(PdoChannel::PDO2), 1500);
+        assert_eq!(config.pdo_voltage(PdoChannel::PDO3), 20000);
+        assert_eq!(config.pdo_current_milliamps(PdoChannel::PDO3), 1000);
+        assert_eq!(config.flex_current(), 2000);
+    }
+
+    #[test]
+    fn default_nvm_tolerances() {
+        let config = NvmConfig::from_sectors(DEFAULT_NVM);
+        let percent = |tolerance: VoltageTolerance| tolerance.percent();
+        assert_eq!(percent(config.upper_tolerance(PdoChannel::PDO1)), 15);
+        assert_eq!(percent(config.lower_tolerance(PdoChannel::PDO1)), 20);
+        assert_eq!(percent(config.upper_tolerance(PdoChannel::PDO2)), 10);
+        assert_eq!(percent(config.lower_tolerance(PdoChannel::PDO2)), 20);
+        assert_eq!(percent(config.upper_tolerance(PdoChannel::PDO3)), 20);
+        assert_eq!(percent(config.lower_tolerance(PdoChannel::PDO3)), 10);
+    }
+
+    #[test]
+    fn tolerance_fields_do_not_overlap_currents() {
+        let mut config = NvmConfig::from_sectors(DEFAULT_NVM);
+        for pdo in [PdoChannel::PDO1, PdoChannel::PDO2, PdoChannel::PDO3] {
+            config.set_upper_tolerance(pdo, VoltageTolerance::MIN);
+            config.set_lower_tolerance(pdo, VoltageTolerance::MAX);
+        }
+        assert_eq!(config.pdo_current_milliamps(PdoChannel::PDO1), 1500);
+        assert_eq!(config.pdo_current_milliamps(PdoChannel::PDO2), 1500);
+        assert_eq!(config.pdo_current_milliamps(PdoChannel::PDO3), 1000);
+        assert_eq!(config.sectors()[3][3], 0x0F);
+        assert_eq!(config.sectors()[3][6], 0xF0);
+    }
+
+    #[test]
+    fn default_nvm_gpio_function() {
+        let mut config = NvmConfig::from_sectors(DEFAULT_NVM);
+        assert_eq!(config.gpio_function(), GpioFunction::ErrorRecovery);
+        config.set_gpio_function(GpioFunction::SinkPower);
+        assert_eq!(config.sectors()[1][0], 0x30);
+    }
+
+    #[test]
+    fn default_nvm_power_ok_config() {
+        let mut config = NvmConfig::from_sectors(DEFAULT_NVM);
+        assert_eq!(config.power_ok_config(), PowerOkConfig::Config2);
+        config.set_power_ok_config(PowerOkConfig::Config3);
+        assert_eq!(config.sectors()[4][4], 0x63);
+    }
+
+    #[test]
+    fn power_ok_outputs() {
+        use PowerOkConfig::*;
+        use PowerOkOutput::*;
+        use PowerOkState::*;
+
+        let rp = Some(RpCurrent::Current3A0);
+        assert_eq!(Config1.asserted_output(1, rp), Released);
+        assert_eq!(Config1.asserted_output(2, rp), Asserted(PowerOk2));
+        assert_eq!(Config1.asserted_output(3, rp), Asserted(PowerOk2));
+        assert_eq!(Config2.asserted_output(0, rp), Released);
+        assert_eq!(Config2.asserted_output(2, rp), Asserted(PowerOk2));
+        assert_eq!(Config2.asserted_output(3, rp), Asserted(PowerOk3));
+        assert_eq!(
+            Config3.asserted_output(0, Some(RpCurrent::DefaultUsb)),
+            Released
+        );
+        assert_eq!(
+            Config3.asserted_output(0, Some(RpCurrent::Current1A5)),
+            Asserted(PowerOk2)
+        );
+        assert_eq!(Config3.asserted_output(3, rp), Asserted(PowerOk3));
+        assert_eq!(Config3.asserted_output(0, None), Released);
+        assert_eq!(NotApplicable.asserted_output(2, rp), Unknown);
+    }
+
+    #[test]
+    fn current_table_edges() {
+        for (milliamps, bits) in [(500, 1), (3000, 11), (5000, 15)] {
+            let current = NvmCurrent::from_milliamps(milliamps).unwrap();
+            assert_eq!(current.bits(), bits);
+            assert_eq!(NvmCurrent::from_bits(bits).milliamps(), Some(milliamps));
+        }
+        assert_eq!(NvmCurrent::from_milliamps(3250), None);
+        assert_eq!(NvmCurrent::FLEX.milliamps(), None);
+    }
+}
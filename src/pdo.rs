@@ -1,22 +1,23 @@
+use core::fmt;
+use core::ops::Deref;
+
 use bitfield::bitfield;
 
-#[derive(Debug, PartialOrd, PartialEq)]
+use crate::rdo::Rdo;
+
+#[derive(Debug, Default, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum FastSwapSupport {
+    #[default]
     NotSupported = 0,
     DefaultUsb = 1,
     _1A5_5V = 2,
     _3A0_5V = 3,
 }
 
-impl Default for FastSwapSupport {
-    fn default() -> Self {
-        FastSwapSupport::NotSupported
-    }
-}
-
-impl Into<u32> for FastSwapSupport {
-    fn into(self) -> u32 {
-        match self {
+impl From<FastSwapSupport> for u32 {
+    fn from(value: FastSwapSupport) -> u32 {
+        match value {
             FastSwapSupport::NotSupported => 0,
             FastSwapSupport::DefaultUsb => 1,
             FastSwapSupport::_1A5_5V => 2,
@@ -28,6 +29,8 @@ impl Into<u32> for FastSwapSupport {
 const PDO_SNK_FIXED: u32 = 0x0 << 30;
 
 bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct FixedPdo(u32);
     impl Debug;
     // The fields default to u16
@@ -56,10 +59,49 @@ impl FixedPdo {
         pdo.set_current(current as u32);
         pdo
     }
+
+    pub fn with_dual_role_power(mut self, dual: bool) -> Self {
+        self.set_dual_role_power(dual);
+        self
+    }
+
+    pub fn with_dual_role_data(mut self, dual: bool) -> Self {
+        self.set_dual_role_data(dual);
+        self
+    }
+
+    pub fn with_usb_communications_capable(mut self, capable: bool) -> Self {
+        self.set_usb_communications_capable(capable);
+        self
+    }
+
+    pub fn with_higher_capability(mut self, capable: bool) -> Self {
+        self.set_higher_capability(capable);
+        self
+    }
+
+    pub fn with_unconstrained_power(mut self, unconstrained: bool) -> Self {
+        self.set_unconstrained_power(unconstrained);
+        self
+    }
+
+    /// Current needed from the new source after a fast role swap, only allowed on PDO1
+    pub fn with_fast_role_swap(mut self, support: FastSwapSupport) -> Self {
+        self.set_fast_role_swap(support.into());
+        self
+    }
 }
 
-const PDO_SNK_VARIABLE: u32 = 0x1 << 30;
+impl From<FixedPdo> for Pdo {
+    fn from(pdo: FixedPdo) -> Self {
+        Pdo::Fixed(pdo)
+    }
+}
+
+const PDO_SNK_VARIABLE: u32 = 0x2 << 30;
 bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct VariablePdo(u32);
     impl Debug;
     // The fields default to u16
@@ -75,8 +117,10 @@ impl Default for VariablePdo {
     }
 }
 
-const PDO_SNK_BATTERY: u32 = 0x2 << 30;
+const PDO_SNK_BATTERY: u32 = 0x1 << 30;
 bitfield! {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
     pub struct BatteryPdo(u32);
     impl Debug;
     // The fields default to u16
@@ -91,6 +135,13 @@ impl Default for BatteryPdo {
     }
 }
 
+/// Power Data Object
+///
+/// The STUSB4500 only supports fixed supply PDOs in the `DPM_SNK_PDOx` registers, the variable
+/// and battery variants are only used for decoding. Build sink PDOs with
+/// [`FixedPdo`], whose setters cover the flags that only exist on fixed PDOs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Pdo {
     Fixed(FixedPdo),
     Variable(VariablePdo),
@@ -101,55 +152,492 @@ impl Pdo {
         Pdo::Fixed(FixedPdo::new(voltage, current))
     }
 
-    pub fn dual_role_power(&mut self, dual: bool) -> &mut Self {
-        if let Pdo::Fixed(x) = self {
-            x.set_dual_role_power(dual);
+    pub(crate) fn bits(&self) -> u32 {
+        match self {
+            Pdo::Fixed(a) => a.0,
+            Pdo::Variable(a) => a.0,
+            Pdo::Battery(a) => a.0,
+        }
+    }
+
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits & 0xC000_0000 {
+            PDO_SNK_FIXED => Some(Pdo::Fixed(FixedPdo(bits))),
+            PDO_SNK_VARIABLE => Some(Pdo::Variable(VariablePdo(bits))),
+            PDO_SNK_BATTERY => Some(Pdo::Battery(BatteryPdo(bits))),
+            _ => None,
+        }
+    }
+}
+
+/// Set of up to three sink PDOs, as advertised by the controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SinkPdos {
+    pdos: [Pdo; 3],
+    len: usize,
+}
+
+impl SinkPdos {
+    /// Create from the first `len` entries of `pdos`
+    pub(crate) fn new(pdos: [Pdo; 3], len: usize) -> Self {
+        SinkPdos { pdos, len }
+    }
+
+    pub fn as_slice(&self) -> &[Pdo] {
+        &self.pdos[..self.len]
+    }
+}
+
+impl Deref for SinkPdos {
+    type Target = [Pdo];
+
+    fn deref(&self) -> &[Pdo] {
+        self.as_slice()
+    }
+}
+
+/// PDO advertised by a source in its Source_Capabilities message
+///
+/// Voltages are in mV, currents in mA and power in mW. Flags of fixed PDOs are not decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SourcePdo {
+    Fixed {
+        voltage: u16,
+        current: u16,
+    },
+    Variable {
+        min_voltage: u16,
+        max_voltage: u16,
+        current: u16,
+    },
+    Battery {
+        min_voltage: u16,
+        max_voltage: u16,
+        power: u32,
+    },
+    /// Programmable power supply augmented PDO
+    Pps {
+        min_voltage: u16,
+        max_voltage: u16,
+        current: u16,
+    },
+}
+
+impl SourcePdo {
+    /// Decode a source PDO, `None` for reserved augmented PDO types
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        let field = |shift: u32, mask: u32| ((bits >> shift) & mask) as u16;
+        match bits >> 30 {
+            0b00 => Some(SourcePdo::Fixed {
+                voltage: field(10, 0x3FF) * 50,
+                current: field(0, 0x3FF) * 10,
+            }),
+            0b01 => Some(SourcePdo::Battery {
+                min_voltage: field(10, 0x3FF) * 50,
+                max_voltage: field(20, 0x3FF) * 50,
+                power: field(0, 0x3FF) as u32 * 250,
+            }),
+            0b10 => Some(SourcePdo::Variable {
+                min_voltage: field(10, 0x3FF) * 50,
+                max_voltage: field(20, 0x3FF) * 50,
+                current: field(0, 0x3FF) * 10,
+            }),
+            _ if (bits >> 28) & 0b11 == 0b00 => Some(SourcePdo::Pps {
+                min_voltage: field(8, 0xFF) * 100,
+                max_voltage: field(17, 0xFF) * 100,
+                current: field(0, 0x7F) * 50,
+            }),
+            _ => None,
         }
-        self
     }
 
-    pub fn dual_role_data(&mut self, dual: bool) -> &mut Self {
-        if let Pdo::Fixed(x) = self {
-            x.set_dual_role_data(dual);
+    pub fn bits(&self) -> u32 {
+        match *self {
+            SourcePdo::Fixed { voltage, current } => {
+                (voltage as u32 / 50) << 10 | (current as u32 / 10)
+            }
+            SourcePdo::Battery {
+                min_voltage,
+                max_voltage,
+                power,
+            } => {
+                0b01 << 30
+                    | (max_voltage as u32 / 50) << 20
+                    | (min_voltage as u32 / 50) << 10
+                    | (power / 250)
+            }
+            SourcePdo::Variable {
+                min_voltage,
+                max_voltage,
+                current,
+            } => {
+                0b10 << 30
+                    | (max_voltage as u32 / 50) << 20
+                    | (min_voltage as u32 / 50) << 10
+                    | (current as u32 / 10)
+            }
+            SourcePdo::Pps {
+                min_voltage,
+                max_voltage,
+                current,
+            } => {
+                0b11 << 30
+                    | (max_voltage as u32 / 100) << 17
+                    | (min_voltage as u32 / 100) << 8
+                    | (current as u32 / 50)
+            }
         }
-        self
     }
 
-    pub fn usb_communications_capable(&mut self, capable: bool) -> &mut Self {
-        if let Pdo::Fixed(x) = self {
-            x.set_usb_communications_capable(capable);
+    /// Maximum power the PDO can deliver in mW
+    pub fn max_power(&self) -> u32 {
+        match *self {
+            SourcePdo::Fixed { voltage, current } => voltage as u32 * current as u32 / 1000,
+            SourcePdo::Battery { power, .. } => power,
+            SourcePdo::Variable {
+                max_voltage,
+                current,
+                ..
+            }
+            | SourcePdo::Pps {
+                max_voltage,
+                current,
+                ..
+            } => max_voltage as u32 * current as u32 / 1000,
         }
-        self
     }
+}
+
+/// Capabilities advertised by a source, up to seven PDOs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SourceCapabilities {
+    pdos: [SourcePdo; 7],
+    len: usize,
+}
 
-    pub fn higher_capability(&mut self, capable: bool) -> &mut Self {
-        if let Pdo::Fixed(x) = self {
-            x.set_higher_capability(capable);
+impl SourceCapabilities {
+    /// Create from a list of PDOs, `None` if empty or longer than seven entries
+    pub fn new(pdos: &[SourcePdo]) -> Option<Self> {
+        if pdos.is_empty() || pdos.len() > 7 {
+            return None;
         }
-        self
+        let mut caps = SourceCapabilities {
+            pdos: [pdos[0]; 7],
+            len: pdos.len(),
+        };
+        caps.pdos[..pdos.len()].copy_from_slice(pdos);
+        Some(caps)
     }
 
-    pub fn unconstrained_power(&mut self, unconstrained: bool) -> &mut Self {
-        if let Pdo::Fixed(x) = self {
-            x.set_unconstrained_power(unconstrained);
+    /// Decode the data objects of a Source_Capabilities message
+    ///
+    /// Returns `None` if any of the objects can not be decoded.
+    pub fn from_data_objects(objects: &[u32]) -> Option<Self> {
+        let mut pdos = [SourcePdo::Fixed {
+            voltage: 0,
+            current: 0,
+        }; 7];
+        for (pdo, bits) in pdos.iter_mut().zip(objects) {
+            *pdo = SourcePdo::from_bits(*bits)?;
         }
-        self
+        SourceCapabilities::new(&pdos[..objects.len().min(7)])
     }
 
-    pub(crate) fn bits(&self) -> u32 {
+    pub fn as_slice(&self) -> &[SourcePdo] {
+        &self.pdos[..self.len]
+    }
+
+    /// PDO at a one-based object position, as used in a request
+    pub fn position(&self, position: usize) -> Option<&SourcePdo> {
+        position.checked_sub(1).and_then(|i| self.as_slice().get(i))
+    }
+
+    /// Highest power offered by any of the PDOs in mW
+    pub fn max_power(&self) -> u32 {
+        self.iter().map(SourcePdo::max_power).max().unwrap_or(0)
+    }
+}
+
+impl Deref for SourceCapabilities {
+    type Target = [SourcePdo];
+
+    fn deref(&self) -> &[SourcePdo] {
+        self.as_slice()
+    }
+}
+
+/// Limit used to derate the sink PDOs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerLimit {
+    /// Maximum power in mW
+    Power(u32),
+    /// Maximum current in mA
+    Current(u32),
+}
+
+impl PowerLimit {
+    /// Highest current in mA allowed at a voltage in mV
+    pub fn current_at(&self, voltage: u32) -> u32 {
+        match *self {
+            PowerLimit::Power(power) => (power as u64 * 1000 / voltage.max(1) as u64) as u32,
+            PowerLimit::Current(current) => current,
+        }
+    }
+
+    /// Check whether the contract described by a request stays within the limit
+    ///
+    /// `caps` are the capabilities the request was made against. Requests for missing or
+    /// programmable power supply PDOs never do.
+    pub fn allows(&self, caps: &SourceCapabilities, rdo: &Rdo) -> bool {
+        let current = rdo.operating_current() * 10;
+        match caps.position(rdo.position() as usize) {
+            Some(SourcePdo::Fixed { voltage, .. }) => current <= self.current_at(*voltage as u32),
+            Some(SourcePdo::Variable { max_voltage, .. }) => {
+                current <= self.current_at(*max_voltage as u32)
+            }
+            // Operating power in 250 mW units
+            Some(SourcePdo::Battery { min_voltage, .. }) => match *self {
+                PowerLimit::Power(power) => rdo.operating_current() as u64 * 250 <= power as u64,
+                PowerLimit::Current(limit) => {
+                    rdo.operating_current() as u64 * 250 * 1000
+                        <= limit as u64 * (*min_voltage).max(1) as u64
+                }
+            },
+            Some(SourcePdo::Pps { .. }) | None => false,
+        }
+    }
+}
+
+/// Lower the current of each sink PDO to stay within `limit`
+///
+/// Currents are rounded down to 10 mA. PDOs above PDO1 that would be left without current are
+/// dropped, and the higher capability flag is cleared if only PDO1 remains. Other flags are
+/// kept.
+pub fn derate_sink_pdos(pdos: &[Pdo], limit: PowerLimit) -> Result<SinkPdos, Violation> {
+    if pdos.is_empty() {
+        return Err(Violation::Empty);
+    }
+    if pdos.len() > 3 {
+        return Err(Violation::TooMany(pdos.len()));
+    }
+
+    let mut derated = [Pdo::new_fixed(0, 0); 3];
+    let mut len = 0;
+    for (index, pdo) in pdos.iter().enumerate() {
+        let mut fixed = match pdo {
+            Pdo::Fixed(fixed) => *fixed,
+            _ => return Err(Violation::NotFixed { index }),
+        };
+        let current = fixed
+            .current()
+            .min(limit.current_at(fixed.voltage() * 50) / 10);
+        match current {
+            0 if index == 0 => return Err(Violation::InvalidCurrent { index, current: 0 }),
+            0 => continue,
+            _ => fixed.set_current(current),
+        }
+        derated[len] = Pdo::Fixed(fixed);
+        len += 1;
+    }
+
+    if let (1, Pdo::Fixed(first)) = (len, &mut derated[0]) {
+        first.set_higher_capability(false);
+    }
+    Ok(SinkPdos::new(derated, len))
+}
+
+/// Highest voltage supported by the STUSB4500, in mV
+pub const MAX_VOLTAGE: u32 = 20000;
+/// Highest current supported by USB-PD, in mA
+pub const MAX_CURRENT: u32 = 5000;
+
+/// USB-PD rule or STUSB4500 limit broken by a sink PDO set
+///
+/// `index` is the zero-based position of the offending PDO.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Violation {
+    /// No PDOs given
+    Empty,
+    /// More than three PDOs given
+    TooMany(usize),
+    /// Only fixed PDOs can be advertised
+    NotFixed { index: usize },
+    /// PDO1 must be the 5 V fixed PDO
+    FirstNot5V { voltage: u32 },
+    /// Voltage of zero or above [`MAX_VOLTAGE`], in mV
+    InvalidVoltage { index: usize, voltage: u32 },
+    /// Current of zero or above [`MAX_CURRENT`], in mA
+    InvalidCurrent { index: usize, current: u32 },
+    /// Voltage not higher than that of the previous PDO
+    NotAscending { index: usize },
+    /// Dual-role, higher capability, unconstrained power, USB communication or fast role swap
+    /// flags set on a PDO other than PDO1
+    FlagsNotInFirst { index: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Pdo::Fixed(a) => a.0,
-            Pdo::Variable(a) => a.0,
-            Pdo::Battery(a) => a.0,
+            Violation::Empty => write!(f, "no PDOs"),
+            Violation::TooMany(num) => write!(f, "{} PDOs, at most 3 allowed", num),
+            Violation::NotFixed { index } => write!(f, "PDO{} is not a fixed PDO", index + 1),
+            Violation::FirstNot5V { voltage } => write!(f, "PDO1 is {} mV instead of 5 V", voltage),
+            Violation::InvalidVoltage { index, voltage } => {
+                write!(f, "PDO{} has invalid voltage {} mV", index + 1, voltage)
+            }
+            Violation::InvalidCurrent { index, current } => {
+                write!(f, "PDO{} has invalid current {} mA", index + 1, current)
+            }
+            Violation::NotAscending { index } => {
+                write!(f, "PDO{} voltage not above previous PDO", index + 1)
+            }
+            Violation::FlagsNotInFirst { index } => {
+                write!(f, "PDO{} has flags only allowed in PDO1", index + 1)
+            }
         }
     }
+}
 
-    pub fn from_bits(bits: u32) -> Option<Self> {
-        match bits & 0xC000_0000 {
-            PDO_SNK_FIXED => Some(Pdo::Fixed(FixedPdo(bits))),
-            PDO_SNK_VARIABLE => Some(Pdo::Variable(VariablePdo(bits))),
-            PDO_SNK_BATTERY => Some(Pdo::Battery(BatteryPdo(bits))),
-            _ => None,
+/// Violations found by [`validate_sink_pdos`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Violations {
+    items: [Violation; Violations::CAPACITY],
+    len: usize,
+}
+
+impl Violations {
+    /// Maximum number of violations reported, further violations are dropped
+    pub const CAPACITY: usize = 16;
+
+    fn new() -> Self {
+        Violations {
+            items: [Violation::Empty; Violations::CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, violation: Violation) {
+        if self.len < Violations::CAPACITY {
+            self.items[self.len] = violation;
+            self.len += 1;
+        }
+    }
+
+    pub fn as_slice(&self) -> &[Violation] {
+        &self.items[..self.len]
+    }
+}
+
+impl fmt::Debug for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Violations {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", self.as_slice())
+    }
+}
+
+impl Deref for Violations {
+    type Target = [Violation];
+
+    fn deref(&self) -> &[Violation] {
+        self.as_slice()
+    }
+}
+
+/// Check a sink PDO set against the USB-PD rules and the STUSB4500 limits
+///
+/// Returns an empty list if the set can be advertised.
+pub fn validate_sink_pdos(pdos: &[Pdo]) -> Violations {
+    let mut violations = Violations::new();
+    match pdos.len() {
+        0 => violations.push(Violation::Empty),
+        1..=3 => {}
+        num => violations.push(Violation::TooMany(num)),
+    }
+
+    let mut last_voltage = 0;
+    for (index, pdo) in pdos.iter().enumerate() {
+        check_sink_pdo(index, pdo, &mut violations);
+        if let Pdo::Fixed(fixed) = pdo {
+            let voltage = fixed.voltage() * 50;
+            if index > 0 && voltage <= last_voltage {
+                violations.push(Violation::NotAscending { index });
+            }
+            last_voltage = voltage;
+        }
+    }
+    violations
+}
+
+/// Check a single sink PDO at its zero-based position in the set
+///
+/// Applies the same rules as [`validate_sink_pdos`], except the ordering against the other
+/// PDOs.
+pub fn validate_sink_pdo(index: usize, pdo: &Pdo) -> Violations {
+    let mut violations = Violations::new();
+    check_sink_pdo(index, pdo, &mut violations);
+    violations
+}
+
+fn check_sink_pdo(index: usize, pdo: &Pdo, violations: &mut Violations) {
+    let fixed = match pdo {
+        Pdo::Fixed(fixed) => fixed,
+        _ => {
+            violations.push(Violation::NotFixed { index });
+            return;
+        }
+    };
+    let voltage = fixed.voltage() * 50;
+    let current = fixed.current() * 10;
+
+    if index == 0 && voltage != 5000 {
+        violations.push(Violation::FirstNot5V { voltage });
+    }
+    if voltage == 0 || voltage > MAX_VOLTAGE {
+        violations.push(Violation::InvalidVoltage { index, voltage });
+    }
+    if current == 0 || current > MAX_CURRENT {
+        violations.push(Violation::InvalidCurrent { index, current });
+    }
+    // Bits 29..23 are only defined for the vSafe5V PDO
+    if index > 0 && fixed.0 & 0x3F80_0000 != 0 {
+        violations.push(Violation::FlagsNotInFirst { index });
+    }
+}
+
+impl fmt::Display for Pdo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pdo::Fixed(pdo) => write!(
+                f,
+                "Fixed {} mV {} mA",
+                pdo.voltage() * 50,
+                pdo.current() * 10
+            ),
+            Pdo::Variable(pdo) => write!(
+                f,
+                "Variable {}-{} mV {} mA",
+                pdo.min_voltage() * 50,
+                pdo.max_voltage() * 50,
+                pdo.current() * 10
+            ),
+            Pdo::Battery(pdo) => write!(
+                f,
+                "Battery {}-{} mV {} mW",
+                pdo.min_voltage() * 50,
+                pdo.max_voltage() * 50,
+                pdo.power() * 250
+            ),
         }
     }
 }
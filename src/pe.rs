@@ -0,0 +1,201 @@
+//! Policy engine watchdog
+//!
+//! Negotiation can get stuck, e.g. when the source never sends its capabilities or the policy
+//! engine keeps cycling through hard resets. [`PeWatchdog`] samples the policy engine state and
+//! reports when it has not reached [`PeState::SnkReady`] for a number of samples.
+//!
+//! Only attached ports are watched, the policy engine stays in its startup states while nothing
+//! is plugged in. Note that a source without USB-PD support never sends capabilities either, so
+//! a stuck negotiation is expected on plain Type-C chargers.
+
+use core::fmt;
+
+use hal::blocking::i2c;
+
+use crate::registers::PeState;
+use crate::{Error, STUSB4500};
+
+/// Diagnostic returned when negotiation is stuck
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct StuckNegotiation<const N: usize> {
+    history: [PeState; N],
+    len: usize,
+    /// Number of consecutive samples without reaching a ready state
+    pub samples: usize,
+}
+
+impl<const N: usize> StuckNegotiation<N> {
+    /// Observed states, oldest first
+    pub fn history(&self) -> &[PeState] {
+        &self.history[..self.len]
+    }
+
+    /// Most recently observed state
+    pub fn state(&self) -> PeState {
+        self.history[self.len - 1]
+    }
+}
+
+impl<const N: usize> fmt::Debug for StuckNegotiation<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StuckNegotiation")
+            .field("samples", &self.samples)
+            .field("history", &self.history())
+            .finish()
+    }
+}
+
+impl<const N: usize> fmt::Display for StuckNegotiation<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "negotiation stuck for {} samples in {}, history:",
+            self.samples,
+            self.state().name()
+        )?;
+        for state in self.history() {
+            write!(f, " {}", state.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// Detects stuck negotiation by sampling the policy engine state
+///
+/// Keeps the last `N` distinct states for the diagnostic, `N` must be at least 1.
+pub struct PeWatchdog<const N: usize> {
+    history: [PeState; N],
+    len: usize,
+    samples: usize,
+    threshold: usize,
+}
+
+impl<const N: usize> PeWatchdog<N> {
+    /// Create a watchdog reporting after `threshold` consecutive samples outside a ready state
+    pub fn new(threshold: usize) -> Self {
+        const { assert!(N > 0, "PeWatchdog needs room for at least one state") };
+        PeWatchdog {
+            history: [PeState::Init; N],
+            len: 0,
+            samples: 0,
+            threshold,
+        }
+    }
+
+    /// Forget the observed states
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.samples = 0;
+    }
+
+    /// Read the port and policy engine state and check them
+    ///
+    /// The watchdog is reset while no source is attached.
+    pub fn sample<I2C, E>(
+        &mut self,
+        stusb: &mut STUSB4500<I2C>,
+    ) -> Result<Option<StuckNegotiation<N>>, Error<E>>
+    where
+        I2C: i2c::Write<Error = E> + i2c::Read<Error = E>,
+    {
+        if !stusb.get_portstatus1()?.attached {
+            self.reset();
+            return Ok(None);
+        }
+        Ok(self.observe(stusb.get_pe_state()?))
+    }
+
+    /// Check a policy engine state obtained elsewhere
+    ///
+    /// Only pass states of an attached port, and call [`Self::reset`] on detach.
+    pub fn observe(&mut self, state: PeState) -> Option<StuckNegotiation<N>> {
+        if state.is_ready() {
+            self.reset();
+            return None;
+        }
+
+        if self.len == 0 || self.history[self.len - 1] != state {
+            if self.len == N {
+                self.history.copy_within(1.., 0);
+                self.len -= 1;
+            }
+            self.history[self.len] = state;
+            self.len += 1;
+        }
+        self.samples += 1;
+
+        if self.samples >= self.threshold {
+            Some(StuckNegotiation {
+                history: self.history,
+                len: self.len,
+                samples: self.samples,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::registers::RpCurrent;
+    use crate::Address;
+
+    #[test]
+    fn reports_after_threshold() {
+        let mut watchdog = PeWatchdog::<4>::new(3);
+        assert_eq!(watchdog.observe(PeState::SnkWaitForCapabilities), None);
+        assert_eq!(watchdog.observe(PeState::SnkWaitForCapabilities), None);
+        let stuck = watchdog.observe(PeState::SnkWaitForCapabilities).unwrap();
+        assert_eq!(stuck.samples, 3);
+        assert_eq!(stuck.history(), [PeState::SnkWaitForCapabilities]);
+        assert_eq!(stuck.state(), PeState::SnkWaitForCapabilities);
+    }
+
+    #[test]
+    fn history_keeps_last_distinct_states() {
+        let mut watchdog = PeWatchdog::<2>::new(4);
+        for state in [
+            PeState::SnkStartup,
+            PeState::SnkDiscovery,
+            PeState::SnkDiscovery,
+            PeState::SnkWaitForCapabilities,
+        ] {
+            watchdog.observe(state);
+        }
+        let stuck = watchdog.observe(PeState::HardReset).unwrap();
+        assert_eq!(stuck.samples, 5);
+        assert_eq!(
+            stuck.history(),
+            [PeState::SnkWaitForCapabilities, PeState::HardReset]
+        );
+    }
+
+    #[test]
+    fn ready_and_reset_restart_counting() {
+        let mut watchdog = PeWatchdog::<4>::new(2);
+        watchdog.observe(PeState::SnkWaitForCapabilities);
+        assert_eq!(watchdog.observe(PeState::SnkReady), None);
+        assert_eq!(watchdog.observe(PeState::SnkWaitForCapabilities), None);
+        watchdog.reset();
+        assert_eq!(watchdog.observe(PeState::SnkWaitForCapabilities), None);
+        assert!(watchdog.observe(PeState::SnkWaitForCapabilities).is_some());
+    }
+
+    #[test]
+    fn detached_port_not_reported() {
+        let mut emulator = Emulator::new(Address::Default);
+        let mut watchdog = PeWatchdog::<4>::new(2);
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        for _ in 0..4 {
+            assert_eq!(watchdog.sample(&mut mcu).unwrap(), None);
+        }
+
+        emulator.attach_type_c(RpCurrent::Current3A0);
+        let mut mcu = STUSB4500::with_bus(&mut emulator, Address::Default);
+        assert_eq!(watchdog.sample(&mut mcu).unwrap(), None);
+        assert!(watchdog.sample(&mut mcu).unwrap().is_some());
+    }
+}
@@ -1,6 +1,11 @@
+use core::fmt;
+
 use bitfield::bitfield;
 
+use crate::registers::{Access, Readable, Register, RegisterValue};
+
 bitfield! {
+  #[cfg_attr(feature = "defmt", derive(defmt::Format))]
   pub struct Rdo(u32);
   impl Debug;
   // The fields default to u16
@@ -14,3 +19,35 @@ bitfield! {
   pub max_operating_current, _: 9, 0;
 
 }
+
+impl RegisterValue for Rdo {
+    const REGISTER: Register = Register::RDORegStatus;
+    const ACCESS: Access = Access::ReadOnly;
+    const RESET: u32 = 0x0000_0000;
+
+    fn from_raw(raw: u32) -> Self {
+        Rdo(raw)
+    }
+
+    fn into_raw(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for Rdo {}
+
+impl fmt::Display for Rdo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "position {}, {} mA (max {} mA)",
+            self.position(),
+            self.operating_current() * 10,
+            self.max_operating_current() * 10
+        )?;
+        if self.capability_mismatch() {
+            write!(f, ", capability mismatch")?;
+        }
+        Ok(())
+    }
+}
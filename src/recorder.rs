@@ -0,0 +1,353 @@
+//! Bus transaction recorder and replay
+//!
+//! [`Recorder`] wraps an I²C bus and appends every transaction to a caller supplied buffer.
+//! [`Replay`] is an I²C bus that plays such a log back, checking that the driver issues the same
+//! writes and returning the recorded read data. Together they turn a trace captured in the field
+//! into a reproducible test.
+//!
+//! Each transaction is encoded as a header of three bytes followed by the data:
+//!
+//! | Byte | Content                                           |
+//! |------|---------------------------------------------------|
+//! | 0    | Kind, see [`RecordKind`]                          |
+//! | 1    | 7-bit device address                              |
+//! | 2    | Data length `n`                                   |
+//! | 3..  | `n` bytes of data written or read                 |
+//!
+//! Failed reads only record the length of the attempted read, without data. Transactions longer
+//! than 255 bytes can not be encoded and are skipped, see [`Recorder::oversized`].
+//!
+//! A [`Recorder`] created with [`Recorder::new`] stops when the buffer is full and keeps the
+//! oldest traffic. One created with [`Recorder::ring`] drops the oldest transactions instead,
+//! to catch what led up to an intermittent failure. Such a log starts in the middle of a
+//! session, so replaying it needs a driver in the same state.
+
+use core::fmt;
+
+use hal::blocking::i2c;
+
+const HEADER_LEN: usize = 3;
+
+/// Kind of a recorded transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecordKind {
+    /// Write, tagged `W`
+    Write,
+    /// Read, tagged `R`
+    Read,
+    /// Write that failed on the bus, tagged `w`
+    WriteError,
+    /// Read that failed on the bus, tagged `r`
+    ReadError,
+}
+
+impl RecordKind {
+    fn tag(self) -> u8 {
+        match self {
+            RecordKind::Write => b'W',
+            RecordKind::Read => b'R',
+            RecordKind::WriteError => b'w',
+            RecordKind::ReadError => b'r',
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            b'W' => Some(RecordKind::Write),
+            b'R' => Some(RecordKind::Read),
+            b'w' => Some(RecordKind::WriteError),
+            b'r' => Some(RecordKind::ReadError),
+            _ => None,
+        }
+    }
+}
+
+/// Single transaction in a log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record<'a> {
+    pub kind: RecordKind,
+    pub address: u8,
+    /// Length of the transaction, only differs from `data.len()` for failed reads
+    pub len: u8,
+    pub data: &'a [u8],
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = self.kind.tag() as char;
+        write!(f, "{} 0x{:02X} [{}]", tag, self.address, self.len)?;
+        for byte in self.data {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the transactions in a log
+///
+/// Stops at the first malformed record.
+#[derive(Clone)]
+pub struct Records<'a> {
+    log: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Records { log }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Record<'a>;
+
+    fn next(&mut self) -> Option<Record<'a>> {
+        if self.log.len() < HEADER_LEN {
+            return None;
+        }
+        let kind = RecordKind::from_tag(self.log[0])?;
+        let address = self.log[1];
+        let len = self.log[2];
+        let data_len = match kind {
+            RecordKind::ReadError => 0,
+            _ => len as usize,
+        };
+        let data = self.log.get(HEADER_LEN..HEADER_LEN + data_len)?;
+        self.log = &self.log[HEADER_LEN + data_len..];
+        Some(Record {
+            kind,
+            address,
+            len,
+            data,
+        })
+    }
+}
+
+/// I²C bus wrapper recording every transaction
+pub struct Recorder<'a, I2C> {
+    i2c: I2C,
+    log: &'a mut [u8],
+    len: usize,
+    ring: bool,
+    overflow: bool,
+    oversized: bool,
+}
+
+impl<'a, I2C> Recorder<'a, I2C> {
+    /// Record until the buffer is full
+    pub fn new(i2c: I2C, log: &'a mut [u8]) -> Self {
+        Recorder {
+            i2c,
+            log,
+            len: 0,
+            ring: false,
+            overflow: false,
+            oversized: false,
+        }
+    }
+
+    /// Record into a ring buffer, dropping the oldest transactions when full
+    pub fn ring(i2c: I2C, log: &'a mut [u8]) -> Self {
+        Recorder {
+            ring: true,
+            ..Recorder::new(i2c, log)
+        }
+    }
+
+    /// The recorded log
+    pub fn log(&self) -> &[u8] {
+        &self.log[..self.len]
+    }
+
+    /// Iterate over the recorded transactions
+    pub fn records(&self) -> Records<'_> {
+        Records::new(self.log())
+    }
+
+    /// Whether transactions were dropped because the buffer was full
+    ///
+    /// In ring mode these are the oldest transactions, otherwise the newest.
+    pub fn overflowed(&self) -> bool {
+        self.overflow
+    }
+
+    /// Whether transactions were skipped because they are longer than 255 bytes
+    pub fn oversized(&self) -> bool {
+        self.oversized
+    }
+
+    /// Discard the recorded transactions
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+        self.oversized = false;
+    }
+
+    /// Destroy the recorder and return the I²C bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn record(&mut self, kind: RecordKind, address: u8, len: usize, data: &[u8]) {
+        if len > u8::MAX as usize {
+            self.oversized = true;
+            return;
+        }
+        let size = HEADER_LEN + data.len();
+        if self.ring && size <= self.log.len() {
+            while self.len + size > self.log.len() {
+                self.drop_oldest();
+            }
+        }
+        let end = self.len + size;
+        if (self.overflow && !self.ring) || end > self.log.len() {
+            self.overflow = true;
+            return;
+        }
+        self.log[self.len] = kind.tag();
+        self.log[self.len + 1] = address;
+        self.log[self.len + 2] = len as u8;
+        self.log[self.len + HEADER_LEN..end].copy_from_slice(data);
+        self.len = end;
+    }
+
+    fn drop_oldest(&mut self) {
+        let size = match Records::new(self.log()).next() {
+            Some(record) => HEADER_LEN + record.data.len(),
+            None => self.len,
+        };
+        self.log.copy_within(size..self.len, 0);
+        self.len -= size;
+        self.overflow = true;
+    }
+}
+
+impl<I2C, E> i2c::Write for Recorder<'_, I2C>
+where
+    I2C: i2c::Write<Error = E>,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        let result = self.i2c.write(address, bytes);
+        let kind = match result {
+            Ok(_) => RecordKind::Write,
+            Err(_) => RecordKind::WriteError,
+        };
+        self.record(kind, address, bytes.len(), bytes);
+        result
+    }
+}
+
+impl<I2C, E> i2c::Read for Recorder<'_, I2C>
+where
+    I2C: i2c::Read<Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        let result = self.i2c.read(address, buffer);
+        match result {
+            Ok(_) => self.record(RecordKind::Read, address, buffer.len(), buffer),
+            Err(_) => self.record(RecordKind::ReadError, address, buffer.len(), &[]),
+        }
+        result
+    }
+}
+
+/// Errors returned by [`Replay`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReplayError {
+    /// The driver issued a transaction that differs from the log
+    Mismatch {
+        /// Index of the transaction in the log
+        index: usize,
+    },
+    /// The driver issued more transactions than recorded
+    Exhausted,
+    /// The recorded transaction failed on the bus
+    Recorded,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Mismatch { index } => write!(f, "transaction {} differs from log", index),
+            ReplayError::Exhausted => write!(f, "no more transactions in log"),
+            ReplayError::Recorded => write!(f, "recorded bus error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReplayError {}
+
+/// I²C bus replaying a log captured by [`Recorder`]
+pub struct Replay<'a> {
+    records: Records<'a>,
+    index: usize,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Replay {
+            records: Records::new(log),
+            index: 0,
+        }
+    }
+
+    /// Number of transactions replayed so far
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    /// Whether all transactions in the log have been replayed
+    pub fn is_done(&self) -> bool {
+        self.records.clone().next().is_none()
+    }
+
+    fn next(&mut self) -> Result<Record<'a>, ReplayError> {
+        let record = self.records.next().ok_or(ReplayError::Exhausted)?;
+        self.index += 1;
+        Ok(record)
+    }
+}
+
+impl i2c::Write for Replay<'_> {
+    type Error = ReplayError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ReplayError> {
+        let index = self.index;
+        let record = self.next()?;
+        if record.address != address || record.data != bytes {
+            return Err(ReplayError::Mismatch { index });
+        }
+        match record.kind {
+            RecordKind::Write => Ok(()),
+            RecordKind::WriteError => Err(ReplayError::Recorded),
+            _ => Err(ReplayError::Mismatch { index }),
+        }
+    }
+}
+
+impl i2c::Read for Replay<'_> {
+    type Error = ReplayError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ReplayError> {
+        let index = self.index;
+        let record = self.next()?;
+        if record.address != address || record.len as usize != buffer.len() {
+            return Err(ReplayError::Mismatch { index });
+        }
+        match record.kind {
+            RecordKind::Read => {
+                buffer.copy_from_slice(record.data);
+                Ok(())
+            }
+            RecordKind::ReadError => Err(ReplayError::Recorded),
+            _ => Err(ReplayError::Mismatch { index }),
+        }
+    }
+}
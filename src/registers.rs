@@ -2,6 +2,8 @@
 
 use bitflags::bitflags;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Register {
     BcdTypeCRevL = 0x06,
     BcdTypeCRevH = 0x07,
@@ -41,7 +43,8 @@ pub enum Register {
     //0x30, Reserved
     RXHeaderL = 0x31,
     RXHeaderH = 0x32,
-    RXDataObj = 0x33, //4 bytes
+    RXDataObj = 0x33, //28 bytes, 7 data objects
+    //0x4F-0x50, Reserved
     TXHeaderL = 0x51,
     TXHeaderH = 0x52,
     RWBuffer = 0x53, // 8 bytes
@@ -57,7 +60,113 @@ pub enum Register {
     NvmCtrl1 = 0x97,
 }
 
+impl Register {
+    /// All registers in address order
+    pub const ALL: [Register; 41] = [
+        Register::BcdTypeCRevL,
+        Register::BcdTypeCRevH,
+        Register::BcdUsbPDRevL,
+        Register::BcdUsbPDRevH,
+        Register::DeviceCapabHigh,
+        Register::AlertStatus1,
+        Register::AlertStatus1Mask,
+        Register::PortStatus0,
+        Register::PortStatus1,
+        Register::TypeCMonitoringStatus0,
+        Register::TypeCMonitoringStatus1,
+        Register::CCStatus,
+        Register::CCHWFaultStatus0,
+        Register::CCHWFaultStatus1,
+        Register::PDTypeCStatus,
+        Register::TypeCStatus,
+        Register::PRTStatus,
+        Register::PDCommandCtrl,
+        Register::MonitoringCtrl0,
+        Register::MonitoringCtrl2,
+        Register::ResetCtrl,
+        Register::VbusDischargeTimeCtrl,
+        Register::VbusDischargeCtrl,
+        Register::VbusCtrl,
+        Register::PEFSM,
+        Register::GpioSWGpio,
+        Register::DeviceId,
+        Register::RXHeaderL,
+        Register::RXHeaderH,
+        Register::RXDataObj,
+        Register::TXHeaderL,
+        Register::TXHeaderH,
+        Register::RWBuffer,
+        Register::DPMPDONumb,
+        Register::DPMSNKPDO1,
+        Register::DPMSNKPDO2,
+        Register::DPMSNKPDO3,
+        Register::RDORegStatus,
+        Register::NvmPassword,
+        Register::NvmCtrl0,
+        Register::NvmCtrl1,
+    ];
+
+    /// Access mode of the register
+    pub const fn access(self) -> Access {
+        match self {
+            Register::PortStatus0
+            | Register::TypeCMonitoringStatus0
+            | Register::CCHWFaultStatus0
+            | Register::PDTypeCStatus
+            | Register::PRTStatus => Access::ReadClear,
+            Register::AlertStatus1Mask
+            | Register::PDCommandCtrl
+            | Register::MonitoringCtrl0
+            | Register::MonitoringCtrl2
+            | Register::ResetCtrl
+            | Register::VbusDischargeTimeCtrl
+            | Register::VbusDischargeCtrl
+            | Register::VbusCtrl
+            | Register::GpioSWGpio
+            | Register::TXHeaderL
+            | Register::TXHeaderH
+            | Register::RWBuffer
+            | Register::DPMPDONumb
+            | Register::DPMSNKPDO1
+            | Register::DPMSNKPDO2
+            | Register::DPMSNKPDO3
+            | Register::NvmPassword
+            | Register::NvmCtrl0
+            | Register::NvmCtrl1 => Access::ReadWrite,
+            _ => Access::ReadOnly,
+        }
+    }
+
+    /// Register address
+    pub fn address(self) -> u8 {
+        self as u8
+    }
+
+    /// Width of the register in bytes
+    pub fn width(self) -> usize {
+        match self {
+            Register::RXDataObj => 28,
+            Register::RWBuffer => 8,
+            Register::DPMSNKPDO1
+            | Register::DPMSNKPDO2
+            | Register::DPMSNKPDO3
+            | Register::RDORegStatus => 4,
+            _ => 1,
+        }
+    }
+
+    /// Register covering an address, `None` for reserved addresses
+    pub fn containing(address: u8) -> Option<Register> {
+        Register::ALL.into_iter().find(|register| {
+            (register.address() as usize..register.address() as usize + register.width())
+                .contains(&(address as usize))
+        })
+    }
+}
+
 bitflags! {
+    /// Interrupt mask, a set bit disables the corresponding [`Alert`]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct AlertMask: u8 {
         const PortStatus            = 0b0100_0000;
         const TypeCMonitoringStatus = 0b0010_0000;
@@ -68,6 +177,20 @@ bitflags! {
     }
 }
 
+impl AlertMask {
+    /// Mask that enables exactly the `enabled` alert sources
+    ///
+    /// [`Alert::PDTypeCStatus`] has no mask bit and is ignored.
+    pub fn from_enabled(enabled: Alert) -> Self {
+        AlertMask::all() - AlertMask::from_bits_truncate(enabled.bits())
+    }
+
+    /// Alert sources enabled by this mask
+    pub fn enabled(self) -> Alert {
+        Alert::from_bits_truncate((AlertMask::all() - self).bits())
+    }
+}
+
 impl Default for AlertMask {
     fn default() -> Self {
         AlertMask::_Default
@@ -89,17 +212,433 @@ bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct PortStatus0: u8 {
         const AttachTrans           = 0b0000_0001;
-    } 
+    }
+}
+
+/// Type of the attached device (`ATTACHED_DEVICE`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AttachedDevice {
+    None,
+    Sink,
+    DebugAccessory,
+    /// Reserved encoding, with the raw 3-bit value
+    Reserved(u8),
+}
+
+/// Power role of the port (`POWER_MODE`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerMode {
+    Source,
+    Sink,
+}
+
+/// Data role of the port (`DATA_MODE`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataMode {
+    Dfp,
+    Ufp,
+}
+
+/// Decoded `PORT_STATUS_1` register
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PortStatus1 {
+    pub attached_device: AttachedDevice,
+    pub power_mode: PowerMode,
+    pub data_mode: DataMode,
+    pub attached: bool,
+}
+
+impl PortStatus1 {
+    pub fn from_bits(bits: u8) -> Self {
+        PortStatus1 {
+            attached_device: match bits >> 5 {
+                0b000 => AttachedDevice::None,
+                0b001 => AttachedDevice::Sink,
+                0b011 => AttachedDevice::DebugAccessory,
+                other => AttachedDevice::Reserved(other),
+            },
+            power_mode: if bits & 0b0000_1000 != 0 {
+                PowerMode::Sink
+            } else {
+                PowerMode::Source
+            },
+            data_mode: if bits & 0b0000_0100 != 0 {
+                DataMode::Ufp
+            } else {
+                DataMode::Dfp
+            },
+            attached: bits & 0b0000_0001 != 0,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        let device = match self.attached_device {
+            AttachedDevice::None => 0b000,
+            AttachedDevice::Sink => 0b001,
+            AttachedDevice::DebugAccessory => 0b011,
+            AttachedDevice::Reserved(other) => other & 0b111,
+        };
+        device << 5
+            | ((self.power_mode == PowerMode::Sink) as u8) << 3
+            | ((self.data_mode == DataMode::Ufp) as u8) << 2
+            | self.attached as u8
+    }
+}
+
+impl RegisterValue for PortStatus1 {
+    const REGISTER: Register = Register::PortStatus1;
+    const ACCESS: Access = Access::ReadOnly;
+    const RESET: u32 = 0x00;
+
+    fn from_raw(raw: u32) -> Self {
+        PortStatus1::from_bits(raw as u8)
+    }
+
+    fn into_raw(self) -> u32 {
+        self.bits() as u32
+    }
+}
+
+impl Readable for PortStatus1 {}
+
+/// Termination detected on a CC line while acting as a sink (`CCx_STATE`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CcState {
+    /// No source termination (`SNK.Open`)
+    Open,
+    /// Source advertising default USB current (`SNK.Default`)
+    Default,
+    /// Source advertising 1.5 A (`SNK.Power1.5`)
+    Power1A5,
+    /// Source advertising 3.0 A (`SNK.Power3.0`)
+    Power3A0,
+}
+
+impl CcState {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => CcState::Open,
+            0b01 => CcState::Default,
+            0b10 => CcState::Power1A5,
+            _ => CcState::Power3A0,
+        }
+    }
+
+    /// Current advertised by the source's Rp, `None` if the line is open
+    pub fn rp_current(self) -> Option<RpCurrent> {
+        match self {
+            CcState::Open => None,
+            CcState::Default => Some(RpCurrent::DefaultUsb),
+            CcState::Power1A5 => Some(RpCurrent::Current1A5),
+            CcState::Power3A0 => Some(RpCurrent::Current3A0),
+        }
+    }
+}
+
+/// Type-C current advertised by a source through its Rp termination
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RpCurrent {
+    /// Default USB power, 500 mA for USB 2.0 and 900 mA for USB 3.x
+    DefaultUsb,
+    Current1A5,
+    Current3A0,
+}
+
+impl RpCurrent {
+    /// Current that may be drawn in mA, assuming USB 2.0 for the default USB power
+    pub fn milliamps(self) -> u16 {
+        match self {
+            RpCurrent::DefaultUsb => 500,
+            RpCurrent::Current1A5 => 1500,
+            RpCurrent::Current3A0 => 3000,
+        }
+    }
+}
+
+/// Decoded `CC_STATUS` register
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CcStatus {
+    pub cc1: CcState,
+    pub cc2: CcState,
+    /// Presenting Rd, i.e. connected as a sink (`CONNECT_RESULT`)
+    pub connect_result: bool,
+    /// Looking for a connection (`LOOKING_4_CONNECTION`)
+    pub looking_for_connection: bool,
+}
+
+impl CcStatus {
+    pub fn from_bits(bits: u8) -> Self {
+        CcStatus {
+            cc1: CcState::from_bits(bits),
+            cc2: CcState::from_bits(bits >> 2),
+            connect_result: bits & 0b0001_0000 != 0,
+            looking_for_connection: bits & 0b0010_0000 != 0,
+        }
+    }
+
+    /// Current advertised on whichever CC line is terminated by the source
+    pub fn rp_current(&self) -> Option<RpCurrent> {
+        self.cc1.rp_current().or(self.cc2.rp_current())
+    }
+}
+
+impl RegisterValue for CcStatus {
+    const REGISTER: Register = Register::CCStatus;
+    const ACCESS: Access = Access::ReadOnly;
+    const RESET: u32 = 0x00;
+
+    fn from_raw(raw: u32) -> Self {
+        CcStatus::from_bits(raw as u8)
+    }
+
+    fn into_raw(self) -> u32 {
+        (self.cc1 as u32)
+            | (self.cc2 as u32) << 2
+            | (self.connect_result as u32) << 4
+            | (self.looking_for_connection as u32) << 5
+    }
+}
+
+impl Readable for CcStatus {}
+
+/// Decoded `TYPEC_STATUS` register
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TypeCStatus {
+    /// Plug is flipped and CC2 is the active line (`REVERSE`)
+    pub reverse: bool,
+    /// Raw Type-C state machine state (`TYPEC_FSM_STATE`)
+    pub fsm_state: u8,
+}
+
+impl TypeCStatus {
+    pub fn from_bits(bits: u8) -> Self {
+        TypeCStatus {
+            reverse: bits & 0b1000_0000 != 0,
+            fsm_state: bits & 0b0001_1111,
+        }
+    }
+}
+
+impl RegisterValue for TypeCStatus {
+    const REGISTER: Register = Register::TypeCStatus;
+    const ACCESS: Access = Access::ReadOnly;
+    const RESET: u32 = 0x00;
+
+    fn from_raw(raw: u32) -> Self {
+        TypeCStatus::from_bits(raw as u8)
+    }
+
+    fn into_raw(self) -> u32 {
+        (self.reverse as u32) << 7 | self.fsm_state as u32
+    }
+}
+
+impl Readable for TypeCStatus {}
+
+/// CC line of the Type-C connector
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CcLine {
+    Cc1,
+    Cc2,
+}
+
+/// Cable orientation and CC line states
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CcInfo {
+    /// Line used for USB-PD communication, `None` if nothing is attached
+    pub active: Option<CcLine>,
+    /// Plug is flipped
+    pub reversed: bool,
+    pub cc1: CcState,
+    pub cc2: CcState,
+}
+
+impl CcInfo {
+    pub fn new(cc_status: CcStatus, typec_status: TypeCStatus) -> Self {
+        let attached = cc_status.cc1 != CcState::Open || cc_status.cc2 != CcState::Open;
+        CcInfo {
+            active: match (attached, typec_status.reverse) {
+                (false, _) => None,
+                (true, false) => Some(CcLine::Cc1),
+                (true, true) => Some(CcLine::Cc2),
+            },
+            reversed: typec_status.reverse,
+            cc1: cc_status.cc1,
+            cc2: cc_status.cc2,
+        }
+    }
+}
+
+/// Policy engine state (`PE_FSM`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PeState {
+    Init,
+    SoftReset,
+    HardReset,
+    SendSoftReset,
+    BistCarrierMode,
+    SnkStartup,
+    SnkDiscovery,
+    SnkWaitForCapabilities,
+    SnkEvaluateCapabilities,
+    SnkSelectCapabilities,
+    SnkTransitionSink,
+    SnkReady,
+    SnkReadySending,
+    HardResetShutdown,
+    HardResetRecovery,
+    ErrorRecovery,
+    /// Undocumented state, with the raw value
+    Unknown(u8),
+}
+
+impl PeState {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0x00 => PeState::Init,
+            0x01 => PeState::SoftReset,
+            0x02 => PeState::HardReset,
+            0x03 => PeState::SendSoftReset,
+            0x04 => PeState::BistCarrierMode,
+            0x12 => PeState::SnkStartup,
+            0x13 => PeState::SnkDiscovery,
+            0x14 => PeState::SnkWaitForCapabilities,
+            0x15 => PeState::SnkEvaluateCapabilities,
+            0x16 => PeState::SnkSelectCapabilities,
+            0x17 => PeState::SnkTransitionSink,
+            0x18 => PeState::SnkReady,
+            0x19 => PeState::SnkReadySending,
+            0x3A => PeState::HardResetShutdown,
+            0x3B => PeState::HardResetRecovery,
+            0x40 => PeState::ErrorRecovery,
+            other => PeState::Unknown(other),
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        match self {
+            PeState::Init => 0x00,
+            PeState::SoftReset => 0x01,
+            PeState::HardReset => 0x02,
+            PeState::SendSoftReset => 0x03,
+            PeState::BistCarrierMode => 0x04,
+            PeState::SnkStartup => 0x12,
+            PeState::SnkDiscovery => 0x13,
+            PeState::SnkWaitForCapabilities => 0x14,
+            PeState::SnkEvaluateCapabilities => 0x15,
+            PeState::SnkSelectCapabilities => 0x16,
+            PeState::SnkTransitionSink => 0x17,
+            PeState::SnkReady => 0x18,
+            PeState::SnkReadySending => 0x19,
+            PeState::HardResetShutdown => 0x3A,
+            PeState::HardResetRecovery => 0x3B,
+            PeState::ErrorRecovery => 0x40,
+            PeState::Unknown(other) => other,
+        }
+    }
+
+    /// Name of the state as used in the USB-PD specification
+    pub fn name(self) -> &'static str {
+        match self {
+            PeState::Init => "PE_INIT",
+            PeState::SoftReset => "PE_SOFT_RESET",
+            PeState::HardReset => "PE_HARD_RESET",
+            PeState::SendSoftReset => "PE_SEND_SOFT_RESET",
+            PeState::BistCarrierMode => "PE_BIST_CARRIER_MODE",
+            PeState::SnkStartup => "PE_SNK_STARTUP",
+            PeState::SnkDiscovery => "PE_SNK_DISCOVERY",
+            PeState::SnkWaitForCapabilities => "PE_SNK_WAIT_FOR_CAPABILITIES",
+            PeState::SnkEvaluateCapabilities => "PE_SNK_EVALUATE_CAPABILITY",
+            PeState::SnkSelectCapabilities => "PE_SNK_SELECT_CAPABILITY",
+            PeState::SnkTransitionSink => "PE_SNK_TRANSITION_SINK",
+            PeState::SnkReady => "PE_SNK_READY",
+            PeState::SnkReadySending => "PE_SNK_READY_SENDING",
+            PeState::HardResetShutdown => "PE_HARD_RESET_SHUTDOWN",
+            PeState::HardResetRecovery => "PE_HARD_RESET_RECOVERY",
+            PeState::ErrorRecovery => "PE_ERROR_RECOVERY",
+            PeState::Unknown(_) => "UNKNOWN",
+        }
+    }
+
+    /// Whether an explicit contract is in place and negotiation has finished
+    pub fn is_ready(self) -> bool {
+        matches!(self, PeState::SnkReady | PeState::SnkReadySending)
+    }
+}
+
+impl RegisterValue for PeState {
+    const REGISTER: Register = Register::PEFSM;
+    const ACCESS: Access = Access::ReadOnly;
+    const RESET: u32 = 0x00;
+
+    fn from_raw(raw: u32) -> Self {
+        PeState::from_bits(raw as u8)
+    }
+
+    fn into_raw(self) -> u32 {
+        self.bits() as u32
+    }
 }
 
+impl Readable for PeState {}
+
 bitflags! {
+    /// Hardware fault transitions, cleared on read (`CC_HW_FAULT_STATUS_0`)
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    pub struct PortStatus1: u8 {
-        const SinkAttached          = 0b0010_0000;
-        const DebugAttached         = 0b0110_0000;
-        const SinkingPower          = 0b0000_1000;
-        const DataModeUFP           = 0b0000_0100;
-        const Attached              = 0b0000_0001;
+    pub struct CCHWFaultStatus0: u8 {
+        const VpuOvpFaultTrans      = 0b0010_0000;
+        const VpuValidTrans         = 0b0001_0000;
+    }
+}
+
+bitflags! {
+    /// Active hardware faults (`CC_HW_FAULT_STATUS_1`)
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct CCHWFaultStatus1: u8 {
+        /// Over-voltage on the CC pins' pull-up supply
+        const VpuOvpFault           = 0b1000_0000;
+        /// CC pins' pull-up supply is valid
+        const VpuValid              = 0b0100_0000;
+        /// VBUS discharge did not complete in time
+        const VbusDischFault        = 0b0001_0000;
+    }
+}
+
+/// Hardware fault state, see [`STUSB4500::get_hw_faults`](crate::STUSB4500::get_hw_faults)
+///
+/// The STUSB4500 does not report thermal faults through these registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HwFaults {
+    /// Currently active fault conditions
+    pub active: CCHWFaultStatus1,
+    /// Transitions since the last read
+    pub transitions: CCHWFaultStatus0,
+}
+
+impl HwFaults {
+    pub fn from_bits(status0: u8, status1: u8) -> Self {
+        HwFaults {
+            active: CCHWFaultStatus1::from_bits_truncate(status1),
+            transitions: CCHWFaultStatus0::from_bits_truncate(status0),
+        }
+    }
+
+    /// Whether a fault is active
+    pub fn is_faulted(&self) -> bool {
+        self.active
+            .intersects(CCHWFaultStatus1::VpuOvpFault | CCHWFaultStatus1::VbusDischFault)
     }
 }
 
@@ -131,6 +670,131 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct GpioSWGpio: u8 {
+        /// Drive the open-drain GPIO pin low
+        const Gpio                  = 0b0000_0001;
+    }
+}
+
+/// VBUS monitoring tolerance in percent of the nominal voltage
+///
+/// Allowed values are 5 % to 20 % in 1 % steps, encoded as a 4-bit offset from 5 %.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VoltageTolerance(u8);
+
+impl VoltageTolerance {
+    pub const MIN: VoltageTolerance = VoltageTolerance(5);
+    pub const MAX: VoltageTolerance = VoltageTolerance(20);
+
+    /// Create a tolerance from a percentage, `None` if outside of 5 % to 20 %
+    pub fn new(percent: u8) -> Option<Self> {
+        match percent {
+            5..=20 => Some(VoltageTolerance(percent)),
+            _ => None,
+        }
+    }
+
+    pub fn percent(self) -> u8 {
+        self.0
+    }
+
+    /// Create from the raw 4-bit encoding
+    pub fn from_bits(bits: u8) -> Self {
+        VoltageTolerance((bits & 0x0F) + 5)
+    }
+
+    /// Raw 4-bit encoding
+    pub fn bits(self) -> u8 {
+        self.0 - 5
+    }
+}
+
+/// VBUS monitoring window of the active contract (`MONITORING_CTRL_2`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MonitoringCtrl2 {
+    /// Over-voltage tolerance (`VSHIFT_HIGH`)
+    pub upper: VoltageTolerance,
+    /// Under-voltage tolerance (`VSHIFT_LOW`)
+    pub lower: VoltageTolerance,
+}
+
+impl MonitoringCtrl2 {
+    pub fn from_bits(bits: u8) -> Self {
+        MonitoringCtrl2 {
+            upper: VoltageTolerance::from_bits(bits >> 4),
+            lower: VoltageTolerance::from_bits(bits),
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.upper.bits() << 4 | self.lower.bits()
+    }
+}
+
+impl RegisterValue for MonitoringCtrl2 {
+    const REGISTER: Register = Register::MonitoringCtrl2;
+    const ACCESS: Access = Access::ReadWrite;
+    const RESET: u32 = 0x00;
+
+    fn from_raw(raw: u32) -> Self {
+        MonitoringCtrl2::from_bits(raw as u8)
+    }
+
+    fn into_raw(self) -> u32 {
+        self.bits() as u32
+    }
+}
+
+impl Readable for MonitoringCtrl2 {}
+impl Writable for MonitoringCtrl2 {}
+
+/// Number of advertised sink PDOs (`DPM_PDO_NUMB`)
+///
+/// Loaded from the NVM at start-up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DpmPdoNumb(u8);
+
+impl DpmPdoNumb {
+    /// Create from a number of PDOs, `None` if outside of 1 to 3
+    pub fn new(num: u8) -> Option<Self> {
+        match num {
+            1..=3 => Some(DpmPdoNumb(num)),
+            _ => None,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        DpmPdoNumb(bits & 0x07)
+    }
+
+    /// Number of PDOs, may be out of range when read from the controller
+    pub fn num(self) -> u8 {
+        self.0
+    }
+}
+
+impl RegisterValue for DpmPdoNumb {
+    const REGISTER: Register = Register::DPMPDONumb;
+    const ACCESS: Access = Access::ReadWrite;
+    const RESET: u32 = 0x00;
+
+    fn from_raw(raw: u32) -> Self {
+        DpmPdoNumb::from_bits(raw as u8)
+    }
+
+    fn into_raw(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+impl Readable for DpmPdoNumb {}
+impl Writable for DpmPdoNumb {}
+
 bitflags! {
     pub struct NvmCtrl0: u8 {
         const Power   = 0b1000_0000;
@@ -149,6 +813,8 @@ bitflags! {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum NvmCtrl1Opcode {
     ReadSector = 0x00,   // Read the sector data
     LoadPlr = 0x01,      // Load the Program Load Register
@@ -158,3 +824,232 @@ pub enum NvmCtrl1Opcode {
     EraseSectors = 0x05, // Erase the specified sectors
     WriteSector = 0x06,  // Program the sector data to EEPROM
 }
+
+/// Access mode of a register
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+    /// Read-only, cleared by reading
+    ReadClear,
+}
+
+/// Typed value of a register
+///
+/// Used with [`STUSB4500::read`](crate::STUSB4500::read),
+/// [`STUSB4500::write`](crate::STUSB4500::write) and
+/// [`STUSB4500::modify`](crate::STUSB4500::modify). The width of the access is given by
+/// [`Register::width`].
+pub trait RegisterValue: Sized {
+    const REGISTER: Register;
+    const ACCESS: Access;
+    /// Raw value after reset
+    const RESET: u32;
+
+    fn from_raw(raw: u32) -> Self;
+    fn into_raw(self) -> u32;
+
+    /// Value after reset
+    fn reset() -> Self {
+        Self::from_raw(Self::RESET)
+    }
+}
+
+/// Register that can be read
+pub trait Readable: RegisterValue {}
+
+/// Register that can be written
+pub trait Writable: Readable {}
+
+macro_rules! impl_register_bits {
+    ($($name:ident: $register:ident, $access:ident, $reset:expr;)*) => {
+        $(
+            impl RegisterValue for $name {
+                const REGISTER: Register = Register::$register;
+                const ACCESS: Access = Access::$access;
+                const RESET: u32 = $reset;
+
+                fn from_raw(raw: u32) -> Self {
+                    $name::from_bits_truncate(raw as u8)
+                }
+
+                fn into_raw(self) -> u32 {
+                    self.bits() as u32
+                }
+            }
+
+            impl Readable for $name {}
+            impl_register_bits!(@writable $name, $access);
+        )*
+    };
+    (@writable $name:ident, ReadWrite) => {
+        impl Writable for $name {}
+    };
+    (@writable $name:ident, $access:ident) => {};
+}
+
+impl_register_bits! {
+    Alert: AlertStatus1, ReadOnly, 0x00;
+    AlertMask: AlertStatus1Mask, ReadWrite, 0x00;
+    PortStatus0: PortStatus0, ReadClear, 0x00;
+    TypeCMonitoringStatus0: TypeCMonitoringStatus0, ReadClear, 0x00;
+    TypeCMonitoringStatus1: TypeCMonitoringStatus1, ReadOnly, 0x00;
+    PrtStatus: PRTStatus, ReadClear, 0x00;
+    CCHWFaultStatus0: CCHWFaultStatus0, ReadClear, 0x00;
+    CCHWFaultStatus1: CCHWFaultStatus1, ReadOnly, 0x00;
+    GpioSWGpio: GpioSWGpio, ReadWrite, 0x00;
+    NvmCtrl0: NvmCtrl0, ReadWrite, 0x00;
+    NvmCtrl1: NvmCtrl1, ReadWrite, 0x00;
+}
+
+/// Format bitflags registers as their raw bits
+#[cfg(feature = "defmt")]
+macro_rules! impl_format_bits {
+    ($($name:ident),*) => {
+        $(
+            impl defmt::Format for $name {
+                fn format(&self, f: defmt::Formatter) {
+                    defmt::write!(f, "{=str}({=u8:#010b})", stringify!($name), self.bits())
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "defmt")]
+impl_format_bits!(
+    AlertMask,
+    Alert,
+    PortStatus0,
+    TypeCMonitoringStatus0,
+    TypeCMonitoringStatus1,
+    PrtStatus,
+    CCHWFaultStatus0,
+    CCHWFaultStatus1,
+    GpioSWGpio,
+    NvmCtrl0,
+    NvmCtrl1
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_status1_attached_device() {
+        let status = PortStatus1::from_bits(0b0110_1101);
+        assert_eq!(status.attached_device, AttachedDevice::DebugAccessory);
+        assert_eq!(status.power_mode, PowerMode::Sink);
+        assert_eq!(status.data_mode, DataMode::Ufp);
+        assert!(status.attached);
+        assert_eq!(status.bits(), 0b0110_1101);
+
+        let sink = PortStatus1::from_bits(0b0010_0000);
+        assert_eq!(sink.attached_device, AttachedDevice::Sink);
+        assert_eq!(sink.power_mode, PowerMode::Source);
+        assert_eq!(sink.data_mode, DataMode::Dfp);
+        assert!(!sink.attached);
+        assert_eq!(
+            PortStatus1::from_bits(0b0100_0000).attached_device,
+            AttachedDevice::Reserved(0b010)
+        );
+    }
+
+    #[test]
+    fn cc_status_rp_current() {
+        let status = CcStatus::from_bits(0b0001_0010);
+        assert_eq!(status.cc1, CcState::Power1A5);
+        assert_eq!(status.cc2, CcState::Open);
+        assert!(status.connect_result);
+        assert!(!status.looking_for_connection);
+        assert_eq!(status.rp_current(), Some(RpCurrent::Current1A5));
+        assert_eq!(status.into_raw(), 0b0001_0010);
+
+        let flipped = CcStatus::from_bits(0b0000_1100);
+        assert_eq!(flipped.cc2, CcState::Power3A0);
+        assert_eq!(flipped.rp_current(), Some(RpCurrent::Current3A0));
+        assert_eq!(
+            CcStatus::from_bits(0b0000_0100).rp_current(),
+            Some(RpCurrent::DefaultUsb)
+        );
+        assert_eq!(CcStatus::from_bits(0b0010_0000).rp_current(), None);
+        assert_eq!(RpCurrent::Current1A5.milliamps(), 1500);
+    }
+
+    #[test]
+    fn cc_info_orientation() {
+        let typec = |reverse| TypeCStatus {
+            reverse,
+            fsm_state: 0,
+        };
+        let info = CcInfo::new(CcStatus::from_bits(0b0000_1000), typec(true));
+        assert_eq!(info.active, Some(CcLine::Cc2));
+        assert!(info.reversed);
+        assert_eq!(info.cc2, CcState::Power1A5);
+
+        let info = CcInfo::new(CcStatus::from_bits(0b0000_0011), typec(false));
+        assert_eq!(info.active, Some(CcLine::Cc1));
+        assert!(!info.reversed);
+
+        let info = CcInfo::new(CcStatus::from_bits(0b0010_0000), typec(false));
+        assert_eq!(info.active, None);
+    }
+
+    #[test]
+    fn pe_state_unknown_fallback() {
+        assert_eq!(PeState::from_bits(0x18), PeState::SnkReady);
+        assert_eq!(PeState::from_bits(0x7F), PeState::Unknown(0x7F));
+        assert_eq!(PeState::Unknown(0x7F).bits(), 0x7F);
+        assert_eq!(PeState::from_bits(0x7F).name(), "UNKNOWN");
+        for bits in 0..=0xFF {
+            assert_eq!(PeState::from_bits(bits).bits(), bits);
+        }
+    }
+
+    #[test]
+    fn hw_fault_bits() {
+        let faults = HwFaults::from_bits(0b0011_0000, 0b1100_0000);
+        assert_eq!(
+            faults.transitions,
+            CCHWFaultStatus0::VpuOvpFaultTrans | CCHWFaultStatus0::VpuValidTrans
+        );
+        assert_eq!(
+            faults.active,
+            CCHWFaultStatus1::VpuOvpFault | CCHWFaultStatus1::VpuValid
+        );
+        assert!(faults.is_faulted());
+
+        let discharge = HwFaults::from_bits(0x00, 0b0001_0000);
+        assert_eq!(discharge.active, CCHWFaultStatus1::VbusDischFault);
+        assert!(discharge.is_faulted());
+
+        let valid = HwFaults::from_bits(0b0000_1111, 0b0100_1111);
+        assert_eq!(valid.transitions, CCHWFaultStatus0::empty());
+        assert_eq!(valid.active, CCHWFaultStatus1::VpuValid);
+        assert!(!valid.is_faulted());
+    }
+
+    #[test]
+    fn alert_mask_enabled_round_trip() {
+        let maskable = Alert::PortStatus
+            | Alert::TypeCMonitoringStatus
+            | Alert::CCHWFaultStatus
+            | Alert::PRTStatus;
+        for enabled in [
+            Alert::empty(),
+            Alert::PortStatus,
+            Alert::PRTStatus,
+            maskable,
+        ] {
+            assert_eq!(AlertMask::from_enabled(enabled).enabled(), enabled);
+        }
+        assert_eq!(AlertMask::from_enabled(maskable), AlertMask::empty());
+        assert_eq!(AlertMask::from_enabled(Alert::empty()).bits(), 0b0111_0010);
+        assert_eq!(
+            AlertMask::from_enabled(Alert::PDTypeCStatus | Alert::PortStatus).enabled(),
+            Alert::PortStatus
+        );
+        assert_eq!(AlertMask::default().enabled(), Alert::CCHWFaultStatus);
+    }
+}
@@ -0,0 +1,227 @@
+//! Snapshot of the complete register file
+//!
+//! A snapshot is read in a single bus transaction and can be printed with its [`Display`]
+//! implementation, e.g. to attach to a bug report.
+//!
+//! [`Display`]: core::fmt::Display
+
+use core::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::pdo::Pdo;
+use crate::rdo::Rdo;
+use crate::registers::*;
+use crate::PdoChannel;
+
+/// First register in a snapshot
+pub const SNAPSHOT_START: Register = Register::BcdTypeCRevL;
+/// Number of bytes in a snapshot, up to and including [`Register::NvmCtrl1`]
+pub const SNAPSHOT_LEN: usize = Register::NvmCtrl1 as usize - SNAPSHOT_START as usize + 1;
+
+/// Raw copy of the registers from [`Register::BcdTypeCRevL`] to [`Register::NvmCtrl1`]
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegisterSnapshot {
+    raw: [u8; SNAPSHOT_LEN],
+}
+
+impl RegisterSnapshot {
+    pub fn from_raw(raw: [u8; SNAPSHOT_LEN]) -> Self {
+        RegisterSnapshot { raw }
+    }
+
+    /// Raw register contents, starting at [`SNAPSHOT_START`]
+    pub fn raw(&self) -> &[u8; SNAPSHOT_LEN] {
+        &self.raw
+    }
+
+    /// Value of a byte register
+    pub fn register(&self, register: Register) -> u8 {
+        self.raw[register as usize - SNAPSHOT_START as usize]
+    }
+
+    /// Value of a word register, `None` for registers that are not 4 bytes wide
+    pub fn register_word(&self, register: Register) -> Option<u32> {
+        match register.width() {
+            4 => Some(self.word(register)),
+            _ => None,
+        }
+    }
+
+    pub fn device_id(&self) -> u8 {
+        self.register(Register::DeviceId)
+    }
+
+    pub fn alerts(&self) -> Alert {
+        Alert::from_bits_truncate(self.register(Register::AlertStatus1))
+    }
+
+    pub fn alerts_mask(&self) -> AlertMask {
+        AlertMask::from_bits_truncate(self.register(Register::AlertStatus1Mask))
+    }
+
+    pub fn portstatus0(&self) -> PortStatus0 {
+        PortStatus0::from_bits_truncate(self.register(Register::PortStatus0))
+    }
+
+    pub fn portstatus1(&self) -> PortStatus1 {
+        PortStatus1::from_bits(self.register(Register::PortStatus1))
+    }
+
+    pub fn typec_monitoring_status0(&self) -> TypeCMonitoringStatus0 {
+        TypeCMonitoringStatus0::from_bits_truncate(self.register(Register::TypeCMonitoringStatus0))
+    }
+
+    pub fn typec_monitoring_status1(&self) -> TypeCMonitoringStatus1 {
+        TypeCMonitoringStatus1::from_bits_truncate(self.register(Register::TypeCMonitoringStatus1))
+    }
+
+    pub fn vbus_monitoring(&self) -> MonitoringCtrl2 {
+        MonitoringCtrl2::from_bits(self.register(Register::MonitoringCtrl2))
+    }
+
+    pub fn cc_status(&self) -> CcStatus {
+        CcStatus::from_bits(self.register(Register::CCStatus))
+    }
+
+    pub fn typec_status(&self) -> TypeCStatus {
+        TypeCStatus::from_bits(self.register(Register::TypeCStatus))
+    }
+
+    pub fn cc_info(&self) -> CcInfo {
+        CcInfo::new(self.cc_status(), self.typec_status())
+    }
+
+    pub fn hw_faults(&self) -> HwFaults {
+        HwFaults::from_bits(
+            self.register(Register::CCHWFaultStatus0),
+            self.register(Register::CCHWFaultStatus1),
+        )
+    }
+
+    pub fn prt_status(&self) -> PrtStatus {
+        PrtStatus::from_bits_truncate(self.register(Register::PRTStatus))
+    }
+
+    pub fn pe_state(&self) -> PeState {
+        PeState::from_bits(self.register(Register::PEFSM))
+    }
+
+    pub fn num_pdo(&self) -> u8 {
+        DpmPdoNumb::from_bits(self.register(Register::DPMPDONumb)).num()
+    }
+
+    pub fn pdo(&self, pdo: PdoChannel) -> Option<Pdo> {
+        Pdo::from_bits(self.word(pdo.register()))
+    }
+
+    pub fn rdo(&self) -> Rdo {
+        Rdo(self.word(Register::RDORegStatus))
+    }
+
+    fn word(&self, register: Register) -> u32 {
+        let start = register as usize - SNAPSHOT_START as usize;
+        LittleEndian::read_u32(&self.raw[start..start + 4])
+    }
+}
+
+impl fmt::Debug for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegisterSnapshot")
+            .field("raw", &&self.raw[..])
+            .finish()
+    }
+}
+
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "STUSB4500 register snapshot")?;
+        writeln!(f, "  Device ID:       0x{:02X}", self.device_id())?;
+        writeln!(f, "  Alerts:          {:?}", self.alerts())?;
+        writeln!(f, "  Alerts mask:     {:?}", self.alerts_mask())?;
+        writeln!(f, "  Port status 0:   {:?}", self.portstatus0())?;
+        writeln!(f, "  Port status 1:   {:?}", self.portstatus1())?;
+        writeln!(
+            f,
+            "  Monitoring 0:    {:?}",
+            self.typec_monitoring_status0()
+        )?;
+        writeln!(
+            f,
+            "  Monitoring 1:    {:?}",
+            self.typec_monitoring_status1()
+        )?;
+        writeln!(f, "  VBUS window:     {:?}", self.vbus_monitoring())?;
+        writeln!(f, "  CC status:       {:?}", self.cc_status())?;
+        writeln!(f, "  Type-C status:   {:?}", self.typec_status())?;
+        writeln!(f, "  HW faults:       {:?}", self.hw_faults())?;
+        writeln!(f, "  PRT status:      {:?}", self.prt_status())?;
+        let pe_state = self.pe_state();
+        writeln!(
+            f,
+            "  PE FSM:          {} (0x{:02X})",
+            pe_state.name(),
+            pe_state.bits()
+        )?;
+        writeln!(f, "  Sink PDOs:       {}", self.num_pdo())?;
+        for (i, channel) in [PdoChannel::PDO1, PdoChannel::PDO2, PdoChannel::PDO3]
+            .into_iter()
+            .enumerate()
+            .take(self.num_pdo() as usize)
+        {
+            match self.pdo(channel) {
+                Some(pdo) => writeln!(f, "  PDO{}:            {}", i + 1, pdo)?,
+                None => writeln!(
+                    f,
+                    "  PDO{}:            invalid (0x{:08X})",
+                    i + 1,
+                    self.word(channel.register())
+                )?,
+            }
+        }
+        writeln!(f, "  RDO:             {}", self.rdo())?;
+        write!(f, "  Raw:")?;
+        for (i, byte) in self.raw.iter().enumerate() {
+            if i % 16 == 0 {
+                write!(f, "\n    {:02X}:", SNAPSHOT_START as usize + i)?;
+            }
+            write!(f, " {:02X}", byte)?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    fn snapshot(registers: &[(Register, u8)]) -> RegisterSnapshot {
+        let mut raw = [0x00; SNAPSHOT_LEN];
+        for (register, value) in registers {
+            raw[*register as usize - SNAPSHOT_START as usize] = *value;
+        }
+        RegisterSnapshot::from_raw(raw)
+    }
+
+    #[test]
+    fn register_word_only_for_word_registers() {
+        let snapshot = snapshot(&[(Register::RDORegStatus, 0x2C), (Register::NvmCtrl1, 0x01)]);
+        assert_eq!(snapshot.register_word(Register::RDORegStatus), Some(0x2C));
+        assert_eq!(snapshot.register_word(Register::DPMSNKPDO3), Some(0));
+        assert_eq!(snapshot.register_word(Register::NvmPassword), None);
+        assert_eq!(snapshot.register_word(Register::NvmCtrl1), None);
+        assert_eq!(snapshot.register_word(Register::PortStatus1), None);
+    }
+
+    #[test]
+    fn display_lists_advertised_pdos() {
+        let text = snapshot(&[(Register::DPMPDONumb, 2)]).to_string();
+        assert!(text.contains("PDO1:"));
+        assert!(text.contains("PDO2:"));
+        assert!(!text.contains("PDO3:"));
+    }
+}
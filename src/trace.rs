@@ -0,0 +1,520 @@
+//! USB-PD message trace
+//!
+//! [`PdTrace`] records received messages and sent commands with a timestamp from a user supplied
+//! monotonic clock, keeping the last `N` entries. Wrap the bus in a [`TracedBus`] to record every
+//! command written to `PD_COMMAND_CTRL` and every new message the application reads from the RX
+//! registers, whichever driver method accesses them. Messages the application does not read, e.g.
+//! because a later message overwrote them, are not captured. The trace can be exported as text or
+//! CSV to compare it with captures from a protocol analyzer:
+//!
+//! ```text
+//! time_us,dir,type,header,objects
+//! 1200,RX,SourceCapabilities,0x2181,0x0001912C 0x0002D12C
+//! 3400,TX,SoftReset,0x0D,
+//! ```
+//!
+//! `header` is the 16-bit message header for received messages and the `TX_HEADER_L` value for
+//! sent commands. `objects` lists the data objects separated by spaces.
+
+use core::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+use hal::blocking::i2c;
+
+use crate::message::{Header, Message, MessageKind, PdCommand, MAX_DATA_OBJECTS};
+use crate::registers::{PrtStatus, Register};
+use crate::Address;
+
+/// Monotonic clock in microseconds
+pub trait Clock {
+    fn now_us(&mut self) -> u64;
+}
+
+impl<F: FnMut() -> u64> Clock for F {
+    fn now_us(&mut self) -> u64 {
+        self()
+    }
+}
+
+/// Traced event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TraceEvent {
+    /// Message received from the source
+    Received(Message),
+    /// Command sent through `PD_COMMAND_CTRL`
+    Sent(PdCommand),
+}
+
+impl TraceEvent {
+    /// Kind of the received message or of the message sent by the command
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            TraceEvent::Received(message) => message.kind(),
+            TraceEvent::Sent(command) => MessageKind::Control(command.message()),
+        }
+    }
+}
+
+/// Traced event with its timestamp
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TraceEntry {
+    /// Timestamp in microseconds
+    pub timestamp: u64,
+    pub event: TraceEvent,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6}.{:06} ",
+            self.timestamp / 1_000_000,
+            self.timestamp % 1_000_000
+        )?;
+        match &self.event {
+            TraceEvent::Received(message) => {
+                write!(
+                    f,
+                    "RX {} id {}",
+                    self.event.kind(),
+                    message.header.message_id()
+                )?;
+                for object in message.data_objects() {
+                    write!(f, " {:08X}", object)?;
+                }
+                Ok(())
+            }
+            TraceEvent::Sent(command) => write!(
+                f,
+                "TX {} (command 0x{:02X})",
+                self.event.kind(),
+                command.command
+            ),
+        }
+    }
+}
+
+/// Trace of the last `N` USB-PD messages and commands
+pub struct PdTrace<C, const N: usize> {
+    clock: C,
+    entries: [TraceEntry; N],
+    start: usize,
+    len: usize,
+    dropped: usize,
+}
+
+impl<C: Clock, const N: usize> PdTrace<C, N> {
+    pub fn new(clock: C) -> Self {
+        PdTrace {
+            clock,
+            entries: [TraceEntry {
+                timestamp: 0,
+                event: TraceEvent::Sent(PdCommand::SOFT_RESET),
+            }; N],
+            start: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn record_received(&mut self, message: Message) {
+        self.push(TraceEvent::Received(message));
+    }
+
+    pub fn record_sent(&mut self, command: PdCommand) {
+        self.push(TraceEvent::Sent(command));
+    }
+
+    fn push(&mut self, event: TraceEvent) {
+        if N == 0 {
+            self.dropped += 1;
+            return;
+        }
+        let entry = TraceEntry {
+            timestamp: self.clock.now_us(),
+            event,
+        };
+        if self.len == N {
+            self.entries[self.start] = entry;
+            self.start = (self.start + 1) % N;
+            self.dropped += 1;
+        } else {
+            self.entries[(self.start + self.len) % N] = entry;
+            self.len += 1;
+        }
+    }
+
+    /// Recorded entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> + '_ {
+        (0..self.len).map(move |i| &self.entries[(self.start + i) % N])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of entries overwritten because the trace was full
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    /// Export as human readable text, one entry per line
+    pub fn text(&self) -> Text<'_, C, N> {
+        Text(self)
+    }
+
+    /// Export as CSV with a header line, see the [module documentation](self) for the columns
+    pub fn csv(&self) -> Csv<'_, C, N> {
+        Csv(self)
+    }
+}
+
+/// Text export of a [`PdTrace`]
+pub struct Text<'a, C, const N: usize>(&'a PdTrace<C, N>);
+
+impl<C: Clock, const N: usize> fmt::Display for Text<'_, C, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in self.0.entries() {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// CSV export of a [`PdTrace`]
+pub struct Csv<'a, C, const N: usize>(&'a PdTrace<C, N>);
+
+impl<C: Clock, const N: usize> fmt::Display for Csv<'_, C, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "time_us,dir,type,header,objects")?;
+        for entry in self.0.entries() {
+            match &entry.event {
+                TraceEvent::Received(message) => {
+                    write!(
+                        f,
+                        "{},RX,{},0x{:04X},",
+                        entry.timestamp,
+                        entry.event.kind(),
+                        message.header.0
+                    )?;
+                    for (i, object) in message.data_objects().iter().enumerate() {
+                        if i > 0 {
+                            f.write_str(" ")?;
+                        }
+                        write!(f, "0x{:08X}", object)?;
+                    }
+                    writeln!(f)?;
+                }
+                TraceEvent::Sent(command) => writeln!(
+                    f,
+                    "{},TX,{},0x{:02X},",
+                    entry.timestamp,
+                    entry.event.kind(),
+                    command.tx_header
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// I²C bus wrapper recording USB-PD traffic into a [`PdTrace`]
+///
+/// Only transactions with the STUSB4500 at the given address are observed. Commands are recorded
+/// when `PD_COMMAND_CTRL` is written, together with the last value written to `TX_HEADER_L`.
+/// Received messages are recorded when the RX registers are read from `RX_HEADER_L` on after
+/// `PRT_STATUS` reported a new message, as
+/// [`STUSB4500::poll_rx_message`](crate::STUSB4500::poll_rx_message) does. Reading the same
+/// message again does not record it twice, while an identical message resent by the source is
+/// recorded again.
+pub struct TracedBus<I2C, C, const N: usize> {
+    i2c: I2C,
+    address: u8,
+    trace: PdTrace<C, N>,
+    /// Register the next read starts at
+    pointer: Option<u8>,
+    tx_header: u8,
+    /// `PRT_STATUS` reported a message that was not recorded yet
+    message_received: bool,
+}
+
+impl<I2C, C: Clock, const N: usize> TracedBus<I2C, C, N> {
+    pub fn new(i2c: I2C, address: Address, trace: PdTrace<C, N>) -> Self {
+        TracedBus {
+            i2c,
+            address: address.addr(),
+            trace,
+            pointer: None,
+            tx_header: 0x00,
+            message_received: false,
+        }
+    }
+
+    pub fn trace(&self) -> &PdTrace<C, N> {
+        &self.trace
+    }
+
+    pub fn trace_mut(&mut self) -> &mut PdTrace<C, N> {
+        &mut self.trace
+    }
+
+    /// Destroy the wrapper and return the I²C bus and the trace
+    pub fn release(self) -> (I2C, PdTrace<C, N>) {
+        (self.i2c, self.trace)
+    }
+
+    fn observe_write(&mut self, address: u8, bytes: &[u8]) {
+        if address != self.address {
+            return;
+        }
+        let (start, data) = match bytes.split_first() {
+            Some((start, data)) => (*start, data),
+            None => return,
+        };
+        self.pointer = Some(start);
+        for (address, value) in (start..=u8::MAX).zip(data) {
+            if address == Register::TXHeaderL as u8 {
+                self.tx_header = *value;
+            } else if address == Register::PDCommandCtrl as u8 {
+                self.trace.record_sent(PdCommand {
+                    tx_header: self.tx_header,
+                    command: *value,
+                });
+            }
+        }
+    }
+
+    fn observe_read(&mut self, address: u8, data: &[u8]) {
+        if address != self.address {
+            return;
+        }
+        let start = match self.pointer.take() {
+            Some(start) => start,
+            None => return,
+        };
+        let status = (Register::PRTStatus as u8)
+            .checked_sub(start)
+            .and_then(|offset| data.get(offset as usize));
+        if let Some(status) = status {
+            if PrtStatus::from_bits_truncate(*status).contains(PrtStatus::PrlMessageReceived) {
+                self.message_received = true;
+            }
+        }
+
+        if start != Register::RXHeaderL as u8 || !self.message_received || data.len() < 2 {
+            return;
+        }
+        let header = Header(LittleEndian::read_u16(data));
+        let len = (header.num_data_objects() as usize).min(MAX_DATA_OBJECTS);
+        let mut objects = [0; MAX_DATA_OBJECTS];
+        for (object, raw) in objects.iter_mut().zip(data[2..].chunks_exact(4)).take(len) {
+            *object = LittleEndian::read_u32(raw);
+        }
+        // Only complete messages are recorded
+        if data.len() < 2 + 4 * len {
+            return;
+        }
+        self.message_received = false;
+        self.trace
+            .record_received(Message::new(header, &objects[..len]));
+    }
+}
+
+impl<I2C, C, E, const N: usize> i2c::Write for TracedBus<I2C, C, N>
+where
+    I2C: i2c::Write<Error = E>,
+    C: Clock,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        self.i2c.write(address, bytes)?;
+        self.observe_write(address, bytes);
+        Ok(())
+    }
+}
+
+impl<I2C, C, E, const N: usize> i2c::Read for TracedBus<I2C, C, N>
+where
+    I2C: i2c::Read<Error = E>,
+    C: Clock,
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        self.i2c.read(address, buffer)?;
+        self.observe_read(address, buffer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::caps::CapsTracker;
+    use crate::emulator::{chargers, Emulator};
+    use crate::message::{ControlMessage, DataMessage, MessageKind};
+    use crate::{Address, STUSB4500};
+
+    fn traced(emulator: Emulator) -> TracedBus<Emulator, impl Clock, 8> {
+        let mut time = 0;
+        TracedBus::new(
+            emulator,
+            Address::Default,
+            PdTrace::new(move || {
+                time += 1;
+                time
+            }),
+        )
+    }
+
+    #[test]
+    fn commands_from_driver_are_recorded() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut bus = traced(emulator);
+
+        STUSB4500::with_bus(&mut bus, Address::Default)
+            .soft_reset()
+            .unwrap();
+
+        let entries: Vec<_> = bus.trace().entries().copied().collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].timestamp, 1);
+        match entries[0].event {
+            TraceEvent::Sent(command) => {
+                assert_eq!(command, PdCommand::SOFT_RESET);
+                assert_eq!(command.message(), ControlMessage::SoftReset);
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn single_poll_feeds_trace_and_tracker() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut bus = traced(emulator);
+        let mut tracker = CapsTracker::new();
+
+        let mut mcu = STUSB4500::with_bus(&mut bus, Address::Default);
+        let change = tracker.poll(&mut mcu).unwrap().unwrap();
+        assert_eq!(change.new, chargers::laptop_45w());
+        // Accept and PS_RDY follow
+        while mcu.poll_rx_message().unwrap().is_some() {}
+        // Reading the same message again is not recorded twice
+        mcu.get_rx_message().unwrap();
+
+        let kinds: Vec<_> = bus
+            .trace()
+            .entries()
+            .map(|entry| match entry.event {
+                TraceEvent::Received(message) => message.kind(),
+                TraceEvent::Sent(_) => panic!("unexpected command"),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                MessageKind::Data(DataMessage::SourceCapabilities),
+                MessageKind::Control(ControlMessage::Accept),
+                MessageKind::Control(ControlMessage::PsRdy),
+            ]
+        );
+    }
+
+    #[test]
+    fn resent_capabilities_are_recorded_again() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut bus = traced(emulator);
+
+        let mut mcu = STUSB4500::with_bus(&mut bus, Address::Default);
+        while mcu.poll_rx_message().unwrap().is_some() {}
+        bus.i2c.send_source_capabilities(chargers::laptop_45w());
+        let mut mcu = STUSB4500::with_bus(&mut bus, Address::Default);
+        let message = mcu.poll_rx_message().unwrap().unwrap();
+        assert_eq!(message.source_capabilities(), Some(chargers::laptop_45w()));
+
+        let caps = bus
+            .trace()
+            .entries()
+            .filter(|entry| {
+                entry.event.kind() == MessageKind::Data(DataMessage::SourceCapabilities)
+            })
+            .count();
+        assert_eq!(caps, 2);
+    }
+
+    #[test]
+    fn other_devices_are_ignored() {
+        let mut emulator = Emulator::new(Address::Custom(0x29));
+        emulator.attach(chargers::laptop_45w());
+        let mut bus = traced(emulator);
+
+        let mut mcu = STUSB4500::with_bus(&mut bus, Address::Custom(0x29));
+        mcu.soft_reset().unwrap();
+        while mcu.poll_rx_message().unwrap().is_some() {}
+        assert!(bus.trace().is_empty());
+    }
+
+    #[test]
+    fn csv_formats_types_alike() {
+        let mut emulator = Emulator::new(Address::Default);
+        emulator.attach(chargers::laptop_45w());
+        let mut bus = traced(emulator);
+
+        let mut mcu = STUSB4500::with_bus(&mut bus, Address::Default);
+        while mcu.poll_rx_message().unwrap().is_some() {}
+        mcu.soft_reset().unwrap();
+        mcu.poll_rx_message().unwrap();
+        let csv = bus.trace().csv().to_string();
+        let types: Vec<_> = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').nth(2).unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "SourceCapabilities",
+                "Accept",
+                "PsRdy",
+                "SoftReset",
+                "SourceCapabilities"
+            ]
+        );
+    }
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let mut time = 0;
+        let mut trace = PdTrace::<_, 2>::new(move || {
+            time += 1;
+            time
+        });
+        for _ in 0..3 {
+            trace.record_sent(PdCommand::SOFT_RESET);
+        }
+        let timestamps: Vec<_> = trace.entries().map(|entry| entry.timestamp).collect();
+        assert_eq!(timestamps, [2, 3]);
+        assert_eq!(trace.dropped(), 1);
+    }
+}
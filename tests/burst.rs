@@ -0,0 +1,97 @@
+//! Burst register access
+
+use embedded_hal_mock::i2c::{Mock, Transaction};
+use stusb4500::registers::Register;
+use stusb4500::{Address, Error, STUSB4500, STUSB4500_ADDR};
+
+#[test]
+fn burst_write_of_consecutive_registers() {
+    let data: Vec<u8> = (0..12).collect();
+    let mut bytes = vec![Register::DPMSNKPDO1 as u8];
+    bytes.extend_from_slice(&data);
+    let mut i2c = Mock::new(&[Transaction::write(STUSB4500_ADDR, bytes)]);
+    STUSB4500::with_bus(&mut i2c, Address::Default)
+        .write_registers(Register::DPMSNKPDO1, &data)
+        .unwrap();
+    i2c.done();
+}
+
+#[test]
+fn burst_write_across_reserved_address_rejected() {
+    let mut i2c = Mock::new(&[]);
+    let mut mcu = STUSB4500::with_bus(&mut i2c, Address::Default);
+    // 0x21 between MONITORING_CTRL_0 and MONITORING_CTRL_2 is reserved
+    assert!(matches!(
+        mcu.write_registers(Register::MonitoringCtrl0, &[0x00; 3]),
+        Err(Error::InvalidRange {
+            start: Register::MonitoringCtrl0,
+            len: 3
+        })
+    ));
+    // Ends in the middle of DPM_SNK_PDO2
+    assert!(matches!(
+        mcu.write_registers(Register::DPMSNKPDO1, &[0x00; 6]),
+        Err(Error::InvalidRange { len: 6, .. })
+    ));
+    i2c.done();
+}
+
+#[test]
+fn burst_write_longer_than_max_rejected() {
+    let mut i2c = Mock::new(&[]);
+    let result = STUSB4500::with_bus(&mut i2c, Address::Default)
+        .write_registers(Register::DPMSNKPDO1, &[0x00; 33]);
+    assert!(matches!(result, Err(Error::InvalidRange { len: 33, .. })));
+    i2c.done();
+}
+
+#[test]
+fn burst_read_includes_reserved_addresses() {
+    let data: Vec<u8> = (0..8).collect();
+    let mut i2c = Mock::new(&[
+        Transaction::write(STUSB4500_ADDR, vec![Register::MonitoringCtrl0 as u8]),
+        Transaction::read(STUSB4500_ADDR, data.clone()),
+    ]);
+    let mut buf = [0x00; 16];
+    let read = STUSB4500::with_bus(&mut i2c, Address::Default)
+        .read_registers(Register::MonitoringCtrl0, Register::VbusCtrl, &mut buf)
+        .unwrap();
+    assert_eq!(read, &data[..]);
+    i2c.done();
+}
+
+#[test]
+fn burst_read_invalid_ranges_rejected() {
+    let mut i2c = Mock::new(&[]);
+    let mut mcu = STUSB4500::with_bus(&mut i2c, Address::Default);
+    let mut buf = [0x00; 4];
+    assert!(matches!(
+        mcu.read_registers(Register::VbusCtrl, Register::MonitoringCtrl0, &mut buf),
+        Err(Error::InvalidRange { len: 0, .. })
+    ));
+    assert!(matches!(
+        mcu.read_registers(Register::MonitoringCtrl0, Register::VbusCtrl, &mut buf),
+        Err(Error::InvalidRange { len: 4, .. })
+    ));
+    i2c.done();
+}
+
+#[test]
+fn burst_write_to_read_only_registers_rejected() {
+    let mut i2c = Mock::new(&[]);
+    let mut mcu = STUSB4500::with_bus(&mut i2c, Address::Default);
+    for (register, len) in [
+        (Register::PortStatus1, 1),
+        (Register::PEFSM, 1),
+        (Register::RDORegStatus, 4),
+        (Register::PRTStatus, 1),
+        // Runs from DPM_SNK_PDO3 into RDO_REG_STATUS
+        (Register::DPMSNKPDO3, 8),
+    ] {
+        assert!(matches!(
+            mcu.write_registers(register, &vec![0x00; len]),
+            Err(Error::InvalidRange { .. })
+        ));
+    }
+    i2c.done();
+}
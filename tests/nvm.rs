@@ -0,0 +1,42 @@
+//! NVM unlock with a custom password
+
+use embedded_hal_mock::i2c::{Mock, Transaction};
+use stusb4500::registers::{NvmCtrl0, Register};
+use stusb4500::{Address, Error, STUSB4500, STUSB4500_ADDR};
+
+const PASSWORD: u8 = 0x5A;
+
+fn unlock(readback: NvmCtrl0) -> Vec<Transaction> {
+    let enable = (NvmCtrl0::Power | NvmCtrl0::Enable).bits();
+    vec![
+        Transaction::write(STUSB4500_ADDR, vec![Register::NvmPassword as u8, PASSWORD]),
+        Transaction::write(STUSB4500_ADDR, vec![Register::NvmCtrl0 as u8, 0x00]),
+        Transaction::write(STUSB4500_ADDR, vec![Register::NvmCtrl0 as u8, enable]),
+        Transaction::write(STUSB4500_ADDR, vec![Register::NvmCtrl0 as u8]),
+        Transaction::read(STUSB4500_ADDR, vec![readback.bits()]),
+    ]
+}
+
+#[test]
+fn rejected_password_reports_locked() {
+    let mut expectations = unlock(NvmCtrl0::empty());
+    expectations.push(Transaction::write(
+        STUSB4500_ADDR,
+        vec![Register::NvmPassword as u8, 0x00],
+    ));
+    let mut i2c = Mock::new(&expectations);
+    let mut mcu = STUSB4500::with_bus(&mut i2c, Address::Default);
+    assert!(matches!(
+        mcu.unlock_nvm_with_password(PASSWORD),
+        Err(Error::NvmLocked)
+    ));
+    i2c.done();
+}
+
+#[test]
+fn accepted_password_unlocks() {
+    let mut i2c = Mock::new(&unlock(NvmCtrl0::Power | NvmCtrl0::Enable));
+    let mut mcu = STUSB4500::with_bus(&mut i2c, Address::Default);
+    assert!(mcu.unlock_nvm_with_password(PASSWORD).is_ok());
+    i2c.done();
+}
@@ -0,0 +1,140 @@
+//! Writing sink PDOs
+
+use embedded_hal_mock::i2c::{Mock, Transaction};
+use stusb4500::pdo::{
+    validate_sink_pdos, FastSwapSupport, FixedPdo, Pdo, PowerLimit, SourceCapabilities, SourcePdo,
+    Violation,
+};
+use stusb4500::rdo::Rdo;
+use stusb4500::registers::Register;
+use stusb4500::{Address, Error, PdoChannel, STUSB4500, STUSB4500_ADDR};
+
+#[test]
+fn flags_rejected_outside_pdo1() {
+    let mut i2c = Mock::new(&[]);
+    let pdo: Pdo = FixedPdo::new(180, 200).with_dual_role_power(true).into();
+    let result = STUSB4500::with_bus(&mut i2c, Address::Default).set_pdo(PdoChannel::PDO2, &pdo);
+    assert!(matches!(
+        result,
+        Err(Error::PdoViolation(Violation::FlagsNotInFirst { index: 1 }))
+    ));
+    i2c.done();
+}
+
+#[test]
+fn pdo_written_at_its_position() {
+    let pdo = FixedPdo::new(180, 200);
+    let mut bytes = vec![Register::DPMSNKPDO2 as u8];
+    bytes.extend_from_slice(&pdo.0.to_le_bytes());
+    let mut i2c = Mock::new(&[Transaction::write(STUSB4500_ADDR, bytes)]);
+    STUSB4500::with_bus(&mut i2c, Address::Default)
+        .set_pdo(PdoChannel::PDO2, &pdo.into())
+        .unwrap();
+    i2c.done();
+}
+
+fn pdo_bytes(pdos: &[Pdo]) -> Vec<u8> {
+    let mut bytes = vec![Register::DPMSNKPDO1 as u8];
+    for pdo in pdos {
+        let fixed = match pdo {
+            Pdo::Fixed(fixed) => fixed,
+            _ => unreachable!(),
+        };
+        bytes.extend_from_slice(&fixed.0.to_le_bytes());
+    }
+    bytes
+}
+
+fn read_num_pdo(num: u8) -> [Transaction; 2] {
+    [
+        Transaction::write(STUSB4500_ADDR, vec![Register::DPMPDONumb as u8]),
+        Transaction::read(STUSB4500_ADDR, vec![num]),
+    ]
+}
+
+#[test]
+fn growing_pdo_count_written_after_pdos() {
+    let pdos = [
+        Pdo::new_fixed(100, 300),
+        Pdo::new_fixed(180, 300),
+        Pdo::new_fixed(300, 200),
+    ];
+    let mut expected = read_num_pdo(1).to_vec();
+    expected.push(Transaction::write(STUSB4500_ADDR, pdo_bytes(&pdos)));
+    expected.push(Transaction::write(
+        STUSB4500_ADDR,
+        vec![Register::DPMPDONumb as u8, 3],
+    ));
+    let mut i2c = Mock::new(&expected);
+    STUSB4500::with_bus(&mut i2c, Address::Default)
+        .set_sink_pdos(&pdos)
+        .unwrap();
+    i2c.done();
+}
+
+#[test]
+fn shrinking_pdo_count_written_before_pdos() {
+    let pdos = [Pdo::new_fixed(100, 300)];
+    let mut expected = read_num_pdo(3).to_vec();
+    expected.push(Transaction::write(
+        STUSB4500_ADDR,
+        vec![Register::DPMPDONumb as u8, 1],
+    ));
+    expected.push(Transaction::write(STUSB4500_ADDR, pdo_bytes(&pdos)));
+    let mut i2c = Mock::new(&expected);
+    STUSB4500::with_bus(&mut i2c, Address::Default)
+        .set_sink_pdos(&pdos)
+        .unwrap();
+    i2c.done();
+}
+
+#[test]
+fn variable_and_battery_pdos_decoded() {
+    // 9 V to 12 V at 2 A
+    let variable = 0b10 << 30 | 240 << 20 | 180 << 10 | 200;
+    match Pdo::from_bits(variable) {
+        Some(Pdo::Variable(pdo)) => {
+            assert_eq!(pdo.max_voltage(), 240);
+            assert_eq!(pdo.min_voltage(), 180);
+            assert_eq!(pdo.current(), 200);
+        }
+        other => panic!("unexpected PDO {:?}", other),
+    }
+
+    // 5 V to 20 V at 15 W
+    let battery = 0b01 << 30 | 400 << 20 | 100 << 10 | 60;
+    match Pdo::from_bits(battery) {
+        Some(Pdo::Battery(pdo)) => {
+            assert_eq!(pdo.max_voltage(), 400);
+            assert_eq!(pdo.min_voltage(), 100);
+            assert_eq!(pdo.power(), 60);
+        }
+        other => panic!("unexpected PDO {:?}", other),
+    }
+}
+
+#[test]
+fn fast_role_swap_only_on_pdo1() {
+    let pdo1 = FixedPdo::new(100, 300).with_fast_role_swap(FastSwapSupport::_1A5_5V);
+    assert_eq!(pdo1.fast_role_swap(), 2);
+    let pdo2 = FixedPdo::new(180, 300).with_fast_role_swap(FastSwapSupport::DefaultUsb);
+    assert_eq!(
+        validate_sink_pdos(&[pdo1.into(), pdo2.into()]).as_slice(),
+        [Violation::FlagsNotInFirst { index: 1 }]
+    );
+}
+
+#[test]
+fn battery_limit_does_not_overflow() {
+    let caps = SourceCapabilities::new(&[SourcePdo::Battery {
+        min_voltage: 50000,
+        max_voltage: 50000,
+        power: 100_000,
+    }])
+    .unwrap();
+    // 100 W in 250 mW units
+    let rdo = Rdo(1 << 28 | 400 << 10 | 400);
+    assert!(PowerLimit::Current(100_000).allows(&caps, &rdo));
+    assert!(!PowerLimit::Current(1000).allows(&caps, &rdo));
+    assert!(PowerLimit::Power(u32::MAX).allows(&caps, &rdo));
+}
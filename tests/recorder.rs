@@ -0,0 +1,149 @@
+//! Recording a driver session and replaying it as a regression test
+
+use std::io::ErrorKind;
+
+use embedded_hal::blocking::i2c::Write;
+use embedded_hal_mock::i2c::{Mock, Transaction};
+use embedded_hal_mock::MockError;
+use stusb4500::recorder::{RecordKind, Recorder, Replay, ReplayError};
+use stusb4500::registers::{Alert, Register};
+use stusb4500::{Address, Error, STUSB4500, STUSB4500_ADDR};
+
+fn write(register: Register, data: &[u8]) -> Transaction {
+    let mut bytes = vec![register as u8];
+    bytes.extend_from_slice(data);
+    Transaction::write(STUSB4500_ADDR, bytes)
+}
+
+fn read(register: Register, data: &[u8]) -> [Transaction; 2] {
+    [
+        write(register, &[]),
+        Transaction::read(STUSB4500_ADDR, data.to_vec()),
+    ]
+}
+
+/// Record a session reading the alerts and the RDO, followed by a soft reset
+fn record_session(log: &mut [u8]) -> usize {
+    let mut expected = Vec::new();
+    expected.extend(read(Register::AlertStatus1, &[0x40]));
+    expected.extend(read(Register::RDORegStatus, &[0x2C, 0xB1, 0x04, 0x20]));
+    expected.push(write(Register::TXHeaderL, &[0x0D]));
+    expected.push(write(Register::PDCommandCtrl, &[0x26]));
+    let mut mock = Mock::new(&expected);
+
+    let mut recorder = Recorder::new(mock.clone(), log);
+    let mut mcu = STUSB4500::with_bus(&mut recorder, Address::Default);
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::PortStatus);
+    assert_eq!(mcu.get_current_rdo().unwrap().position(), 2);
+    mcu.soft_reset().unwrap();
+    assert!(!recorder.overflowed());
+    let len = recorder.log().len();
+    mock.done();
+    len
+}
+
+#[test]
+fn replay_recorded_session() {
+    let mut log = [0x00; 64];
+    let len = record_session(&mut log);
+
+    let mut replay = Replay::new(&log[..len]);
+    let mut mcu = STUSB4500::with_bus(&mut replay, Address::Default);
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::PortStatus);
+    let rdo = mcu.get_current_rdo().unwrap();
+    assert_eq!(rdo.position(), 2);
+    assert_eq!(rdo.operating_current(), 300);
+    mcu.soft_reset().unwrap();
+
+    assert_eq!(replay.position(), 6);
+    assert!(replay.is_done());
+}
+
+#[test]
+fn replay_detects_diverging_driver() {
+    let mut log = [0x00; 64];
+    let len = record_session(&mut log);
+
+    let mut replay = Replay::new(&log[..len]);
+    let mut mcu = STUSB4500::with_bus(&mut replay, Address::Default);
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::PortStatus);
+    match mcu.get_pe_state() {
+        Err(Error::I2CError {
+            register, error, ..
+        }) => {
+            assert_eq!(register, Register::PEFSM);
+            assert_eq!(error, ReplayError::Mismatch { index: 2 });
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn replay_recorded_bus_error() {
+    let expected = [
+        write(Register::RDORegStatus, &[]),
+        Transaction::read(STUSB4500_ADDR, vec![0x00; 4])
+            .with_error(MockError::Io(ErrorKind::Other)),
+    ];
+    let mut mock = Mock::new(&expected);
+    let mut log = [0x00; 16];
+    let mut recorder = Recorder::new(mock.clone(), &mut log);
+    let result = STUSB4500::with_bus(&mut recorder, Address::Default).get_current_rdo();
+    assert!(matches!(result, Err(Error::I2CError { .. })));
+
+    let kinds: Vec<_> = recorder.records().map(|record| record.kind).collect();
+    assert_eq!(kinds, [RecordKind::Write, RecordKind::ReadError]);
+    let len = recorder.log().len();
+    mock.done();
+
+    let mut replay = Replay::new(&log[..len]);
+    match STUSB4500::with_bus(&mut replay, Address::Default).get_current_rdo() {
+        Err(Error::I2CError {
+            register, error, ..
+        }) => {
+            assert_eq!(register, Register::RDORegStatus);
+            assert_eq!(error, ReplayError::Recorded);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(replay.is_done());
+}
+
+#[test]
+fn oversized_transaction_skipped() {
+    let long = vec![0x00; 300];
+    let expected = [
+        Transaction::write(STUSB4500_ADDR, long.clone()),
+        Transaction::write(STUSB4500_ADDR, vec![0x01]),
+    ];
+    let mut mock = Mock::new(&expected);
+    let mut log = [0x00; 16];
+    let mut recorder = Recorder::new(mock.clone(), &mut log);
+    recorder.write(STUSB4500_ADDR, &long).unwrap();
+    recorder.write(STUSB4500_ADDR, &[0x01]).unwrap();
+
+    assert!(recorder.oversized());
+    assert!(!recorder.overflowed());
+    let data: Vec<_> = recorder.records().map(|record| record.data).collect();
+    assert_eq!(data, [&[0x01][..]]);
+    mock.done();
+}
+
+#[test]
+fn ring_keeps_newest_transactions() {
+    let expected: Vec<_> = (0..5)
+        .map(|i| Transaction::write(STUSB4500_ADDR, vec![i, i]))
+        .collect();
+    let mut mock = Mock::new(&expected);
+    // Room for two records of five bytes
+    let mut log = [0x00; 12];
+    let mut recorder = Recorder::ring(mock.clone(), &mut log);
+    for i in 0..5 {
+        recorder.write(STUSB4500_ADDR, &[i, i]).unwrap();
+    }
+
+    assert!(recorder.overflowed());
+    let data: Vec<_> = recorder.records().map(|record| record.data).collect();
+    assert_eq!(data, [&[3, 3][..], &[4, 4][..]]);
+    mock.done();
+}
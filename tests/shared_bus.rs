@@ -0,0 +1,139 @@
+//! Driver on a bus shared with other devices, through bus proxies
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Read, Write};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_mock::i2c::{Mock, Transaction};
+use stusb4500::gpio::GpioPin;
+use stusb4500::registers::{Alert, Register};
+use stusb4500::{Address, STUSB4500, STUSB4500_ADDR};
+
+const OTHER_ADDR: u8 = 0x50;
+
+/// Proxy handing out access to a bus shared through a `RefCell`
+struct BusProxy<'a, I2C>(&'a RefCell<I2C>);
+
+impl<I2C: Write> Write for BusProxy<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<I2C: Read> Read for BusProxy<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().read(address, buffer)
+    }
+}
+
+fn read_alerts(value: u8) -> [Transaction; 2] {
+    [
+        Transaction::write(STUSB4500_ADDR, vec![0x0B]),
+        Transaction::read(STUSB4500_ADDR, vec![value]),
+    ]
+}
+
+#[test]
+fn proxy_moved_into_driver_and_released() {
+    let mut expected = Vec::new();
+    expected.extend(read_alerts(0x40));
+    expected.push(Transaction::write(OTHER_ADDR, vec![0x01, 0x02]));
+    expected.extend(read_alerts(0x00));
+    expected.push(Transaction::write(OTHER_ADDR, vec![0x03]));
+    let bus = RefCell::new(Mock::new(&expected));
+
+    let mut mcu = STUSB4500::new(BusProxy(&bus), Address::Default);
+    let mut other = BusProxy(&bus);
+
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::PortStatus);
+    other.write(OTHER_ADDR, &[0x01, 0x02]).unwrap();
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::empty());
+
+    let mut proxy = mcu.release();
+    proxy.write(OTHER_ADDR, &[0x03]).unwrap();
+
+    bus.into_inner().done();
+}
+
+#[test]
+fn bus_borrowed_per_call() {
+    let mut expected = Vec::new();
+    expected.extend(read_alerts(0x40));
+    expected.push(Transaction::write(OTHER_ADDR, vec![0x01]));
+    let mut i2c = Mock::new(&expected);
+
+    let alerts = STUSB4500::with_bus(&mut i2c, Address::Default)
+        .get_alerts()
+        .unwrap();
+    assert_eq!(alerts, Alert::PortStatus);
+    i2c.write(OTHER_ADDR, &[0x01]).unwrap();
+
+    i2c.done();
+}
+
+#[test]
+fn gpio_pin_kept_while_driver_in_use() {
+    let gpio = Register::GpioSWGpio as u8;
+    let mut expected = Vec::new();
+    expected.push(Transaction::write(STUSB4500_ADDR, vec![gpio, 0x01]));
+    expected.extend(read_alerts(0x40));
+    expected.push(Transaction::write(STUSB4500_ADDR, vec![gpio, 0x00]));
+    let bus = RefCell::new(Mock::new(&expected));
+
+    let mut pin = GpioPin::new(BusProxy(&bus), Address::Default);
+    let mut mcu = STUSB4500::new(BusProxy(&bus), Address::Default);
+
+    pin.set_low().unwrap();
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::PortStatus);
+    pin.set_high().unwrap();
+
+    bus.into_inner().done();
+}
+
+/// embedded-hal 0.2 view of an embedded-hal 1.0 bus device
+struct Compat<T>(T);
+
+impl<T: embedded_hal_1::i2c::I2c> Write for Compat<T> {
+    type Error = T::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, bytes)
+    }
+}
+
+impl<T: embedded_hal_1::i2c::I2c> Read for Compat<T> {
+    type Error = T::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(address, buffer)
+    }
+}
+
+#[test]
+fn embedded_hal_bus_devices() {
+    use embedded_hal_1::i2c::I2c;
+    use embedded_hal_bus::i2c::RefCellDevice;
+    use embedded_hal_mock_1::eh1::i2c::{Mock, Transaction};
+
+    let gpio = Register::GpioSWGpio as u8;
+    let bus = RefCell::new(Mock::new(&[
+        Transaction::write(STUSB4500_ADDR, vec![0x0B]),
+        Transaction::read(STUSB4500_ADDR, vec![0x40]),
+        Transaction::write(OTHER_ADDR, vec![0x01]),
+        Transaction::write(STUSB4500_ADDR, vec![gpio, 0x01]),
+    ]));
+
+    let mut mcu = STUSB4500::new(Compat(RefCellDevice::new(&bus)), Address::Default);
+    let mut pin = GpioPin::new(Compat(RefCellDevice::new(&bus)), Address::Default);
+    let mut other = RefCellDevice::new(&bus);
+
+    assert_eq!(mcu.get_alerts().unwrap(), Alert::PortStatus);
+    other.write(OTHER_ADDR, &[0x01]).unwrap();
+    pin.set_low().unwrap();
+
+    bus.into_inner().done();
+}